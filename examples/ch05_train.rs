@@ -24,4 +24,11 @@ fn main() {
     let mnist = dlfs::data::Mnist::load(opt.mnist_data_dir);
     let mut net = dlfs::ch05::TwoLayerNet::default();
     net.train(&mnist, opt.iters_num, opt.batch_size, opt.learning_rate);
+
+    let mut oks = 0.0;
+    for (x_batch, t_batch) in mnist.test_batches(opt.batch_size) {
+        let rows = x_batch.rows() as f64;
+        oks += net.accuracy(x_batch, t_batch) * rows;
+    }
+    println!("TEST ACCURACY: {}", oks / (mnist.test_count() as f64));
 }
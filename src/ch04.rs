@@ -44,8 +44,7 @@ impl TwoLayerNet {
         let a1 = xs.dot_product(&self.w1).add(&self.b1);
         let z1 = a1.map(|&v| sigmoid(v));
         let a2 = z1.dot_product(&self.w2).add(&self.b2);
-        let ys = a2.map_row(softmax).into_vector();
-        ys
+        a2.map_row(softmax).into_vector()
     }
 
     pub fn loss(&self, data: &[f64], labels: &[f64]) -> f64 {
@@ -70,7 +69,7 @@ impl TwoLayerNet {
     pub fn accuracy(&self, data: &[f64], labels: &[f64]) -> f64 {
         let ys = self.predict(data);
         let y = argmax(&ys);
-        let t = argmax(&labels);
+        let t = argmax(labels);
         if y == t {
             1.0
         } else {
@@ -85,7 +84,7 @@ impl TwoLayerNet {
             self.w1 = old;
             loss
         });
-        let b1 = self.w1.clone().numerical_gradient(|m| {
+        let b1 = self.b1.clone().numerical_gradient(|m| {
             let old = mem::replace(&mut self.b1, m.clone());
             let loss = self.loss(data, labels);
             self.b1 = old;
@@ -97,7 +96,7 @@ impl TwoLayerNet {
             self.w2 = old;
             loss
         });
-        let b2 = self.w2.clone().numerical_gradient(|m| {
+        let b2 = self.b2.clone().numerical_gradient(|m| {
             let old = mem::replace(&mut self.b2, m.clone());
             let loss = self.loss(data, labels);
             self.b2 = old;
@@ -1,7 +1,9 @@
-use data::Mnist;
+use data::{DataLoader, DataLoaderOptions, Dataset};
 use functions::argmax;
+use gradient::Differentiable;
 use layers::{AffineLayer, ReluLayer, SoftmaxWithLossLayer};
 use matrix::Matrix;
+use optimize::{Optimizer, Pairs, Sgd};
 
 #[derive(Debug)]
 pub struct TwoLayerNet {
@@ -32,8 +34,7 @@ impl TwoLayerNet {
     pub fn predict(&mut self, xs: Matrix) -> Matrix {
         let xs = self.affine1_layer.forward(xs);
         let xs = self.relu1_layer.forward(xs);
-        let xs = self.affine2_layer.forward(xs);
-        xs
+        self.affine2_layer.forward(xs)
     }
 
     pub fn loss(&mut self, x: Matrix, t: Matrix) -> Vec<f64> {
@@ -69,26 +70,104 @@ impl TwoLayerNet {
         Gradients { w1, b1, w2, b2 }
     }
 
-    pub fn train(
+    pub fn update<O: Optimizer>(&mut self, optimizer: &mut O, grad: &Gradients) {
+        optimizer.update(self.pairs(grad).into_iter());
+    }
+
+    pub fn pairs<'a>(&'a mut self, grad: &'a Gradients) -> Vec<Pairs<'a>> {
+        vec![
+            Pairs {
+                name: "affine1.w",
+                param: &mut self.affine1_layer.w,
+                gradient: &grad.w1,
+            },
+            Pairs {
+                name: "affine1.b",
+                param: &mut self.affine1_layer.b,
+                gradient: &grad.b1,
+            },
+            Pairs {
+                name: "affine2.w",
+                param: &mut self.affine2_layer.w,
+                gradient: &grad.w2,
+            },
+            Pairs {
+                name: "affine2.b",
+                param: &mut self.affine2_layer.b,
+                gradient: &grad.b2,
+            },
+        ]
+    }
+
+    pub fn train<D: Dataset>(
         &mut self,
-        mnist: &Mnist,
+        dataset: &D,
         iters_num: usize,
         batch_size: usize,
         learning_rate: f64,
-    ) {
+    ) -> Vec<f64> {
+        let mut optimizer = Sgd::new(learning_rate);
+        self.train_with_optimizer(dataset, iters_num, batch_size, &mut optimizer)
+    }
+
+    pub fn train_with_optimizer<D: Dataset, O: Optimizer>(
+        &mut self,
+        dataset: &D,
+        iters_num: usize,
+        batch_size: usize,
+        optimizer: &mut O,
+    ) -> Vec<f64> {
+        let options = DataLoaderOptions {
+            batch_size,
+            ..Default::default()
+        };
+        let mut loader = DataLoader::new(dataset, options);
+        let mut batches = loader.epoch();
+        let mut losses = Vec::with_capacity(iters_num);
+
         println!("# START: TRAIN");
         for i in 0..iters_num {
-            let (x_batch, t_batch) = mnist.choice_train_batch2(batch_size);
+            let (x_batch, t_batch) = match batches.next() {
+                Some(batch) => batch,
+                None => {
+                    batches = loader.epoch();
+                    batches.next().expect("Empty dataset")
+                }
+            };
             let grad = self.gradient(x_batch.clone(), t_batch.clone());
-            self.affine1_layer.w -= grad.w1 * learning_rate;
-            self.affine1_layer.b -= grad.b1 * learning_rate;
-            self.affine2_layer.w -= grad.w2 * learning_rate;
-            self.affine2_layer.b -= grad.b2 * learning_rate;
+            self.update(optimizer, &grad);
 
             let loss = self.loss(x_batch, t_batch);
             println!("[{}/{}]: LOSS={:?}", i + 1, iters_num, loss);
+            losses.push(loss.iter().sum::<f64>() / (loss.len() as f64));
         }
         println!(" END: TRAIN");
+        losses
+    }
+}
+impl Differentiable for TwoLayerNet {
+    fn parameter_names(&self) -> Vec<&'static str> {
+        vec!["affine1.w", "affine1.b", "affine2.w", "affine2.b"]
+    }
+
+    fn parameter_mut(&mut self, index: usize) -> &mut Matrix {
+        match index {
+            0 => &mut self.affine1_layer.w,
+            1 => &mut self.affine1_layer.b,
+            2 => &mut self.affine2_layer.w,
+            3 => &mut self.affine2_layer.b,
+            _ => panic!("Unknown parameter index: {}", index),
+        }
+    }
+
+    fn loss(&mut self, x: &Matrix, t: &Matrix) -> f64 {
+        let loss = TwoLayerNet::loss(self, x.clone(), t.clone());
+        loss.iter().sum::<f64>() / (loss.len() as f64)
+    }
+
+    fn gradients(&mut self, x: &Matrix, t: &Matrix) -> Vec<Matrix> {
+        let grad = self.gradient(x.clone(), t.clone());
+        vec![grad.w1, grad.b1, grad.w2, grad.b2]
     }
 }
 impl Default for TwoLayerNet {
@@ -104,3 +183,45 @@ pub struct Gradients {
     pub w2: Matrix,
     pub b2: Matrix,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data::MatrixDataset;
+    use gradient::gradient_check;
+    use random;
+
+    #[test]
+    fn gradient_matches_numerical_gradient() {
+        let mut net = TwoLayerNet::new(4, 5, 3);
+        let x = Matrix::from(vec![vec![0.1, -0.5, 0.3, 0.9], vec![-0.7, 0.2, 0.4, -0.1]]);
+        let t = Matrix::from(vec![vec![0.0, 1.0, 0.0], vec![1.0, 0.0, 0.0]]);
+        for e in gradient_check(&mut net, &x, &t) {
+            assert!(e.max_absolute_error < 1e-6, "{:?}", e);
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_loss_curve() {
+        let x = (0..20)
+            .map(|i| {
+                (0..4)
+                    .map(|j| ((i * 7 + j * 3) % 11) as f64 / 11.0)
+                    .collect()
+            })
+            .collect::<Vec<_>>();
+        let t = (0..20)
+            .map(|i| (0..3).map(|j| if i % 3 == j { 1.0 } else { 0.0 }).collect())
+            .collect::<Vec<_>>();
+        let dataset = MatrixDataset::new(Matrix::from(x), Matrix::from(t));
+
+        let train = |seed| {
+            random::seed(seed);
+            TwoLayerNet::new(4, 5, 3).train(&dataset, 10, 4, 0.1)
+        };
+        let losses = train(7);
+        assert_eq!(losses.len(), 10);
+        assert_eq!(losses, train(7));
+        assert_ne!(losses, train(8));
+    }
+}
@@ -0,0 +1,119 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::Dataset;
+use image::{Image, Transform};
+use random;
+
+// Applies `transform` to every sample drawn from `dataset` (e.g., `Augmented::new(&mnist, (1, 28, 28), t)`).
+// Labels are left untouched.
+//
+// The random numbers for the `k`-th draw of the `i`-th sample come from a generator seeded with
+// `(seed, i, k)`, so the result does not depend on which thread assembles which batch
+// (e.g., with `PrefetchLoader`) while every epoch still gets new augmentations.
+#[derive(Debug)]
+pub struct Augmented<D, T> {
+    dataset: D,
+    shape: (usize, usize, usize),
+    transform: T,
+    seed: u64,
+    draws: Vec<AtomicU64>,
+}
+impl<D: Dataset, T: Transform> Augmented<D, T> {
+    // `shape`: (channels, height, width) of the flat inputs of `dataset`
+    pub fn new(dataset: D, shape: (usize, usize, usize), transform: T) -> Self {
+        Self::with_rng(dataset, shape, transform, random::new_rng())
+    }
+
+    // `rng` is only used to pick the base seed
+    pub fn with_rng(
+        dataset: D,
+        shape: (usize, usize, usize),
+        transform: T,
+        mut rng: StdRng,
+    ) -> Self {
+        let draws = (0..dataset.len()).map(|_| AtomicU64::new(0)).collect();
+        Augmented {
+            dataset,
+            shape,
+            transform,
+            seed: rng.gen(),
+            draws,
+        }
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.dataset
+    }
+}
+impl<D: Dataset, T: Transform> Dataset for Augmented<D, T> {
+    fn len(&self) -> usize {
+        self.dataset.len()
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        let (x, t) = self.dataset.get(index);
+        let (channels, height, width) = self.shape;
+        let image = Image::from_slice(channels, height, width, &x).expect("Unexpected input size");
+
+        let draw = self.draws[index].fetch_add(1, Ordering::Relaxed);
+        let seed = mix(mix(self.seed ^ index as u64) ^ draw);
+        let image = self
+            .transform
+            .apply(&image, &mut StdRng::seed_from_u64(seed));
+        (image.into_vec(), t)
+    }
+
+    fn label(&self, index: usize) -> Vec<f64> {
+        self.dataset.label(index)
+    }
+}
+
+// SplitMix64's finalizer (spreads nearby inputs over the whole range)
+fn mix(x: u64) -> u64 {
+    let x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    let x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data::MatrixDataset;
+    use image::{GaussianNoise, HorizontalFlip};
+    use matrix::Matrix;
+
+    #[test]
+    fn augmented_works() {
+        let x = Matrix::from(vec![vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]]);
+        let t = Matrix::from(vec![vec![1.0]]);
+        let dataset = MatrixDataset::new(x, t);
+        let augmented = Augmented::new(&dataset, (1, 2, 3), HorizontalFlip { probability: 1.0 });
+        assert_eq!(augmented.len(), 1);
+        assert_eq!(
+            augmented.get(0),
+            (vec![3.0, 2.0, 1.0, 6.0, 5.0, 4.0], vec![1.0])
+        );
+    }
+
+    #[test]
+    fn draws_are_reproducible() {
+        let x = Matrix::from(vec![vec![0.0; 4]; 3]);
+        let t = Matrix::from(vec![vec![1.0]; 3]);
+        let dataset = MatrixDataset::new(x, t);
+        let noise = || GaussianNoise { std: 1.0 };
+        let a = Augmented::with_rng(&dataset, (1, 2, 2), noise(), StdRng::seed_from_u64(0));
+        let b = Augmented::with_rng(&dataset, (1, 2, 2), noise(), StdRng::seed_from_u64(0));
+
+        // The access order across samples does not matter
+        let a0 = a.get(0);
+        let (b2, b0) = (b.get(2), b.get(0));
+        assert_eq!(a0, b0);
+        assert_eq!(a.get(2), b2);
+
+        // The next draw of the same sample is different
+        assert_ne!(a.get(0), a0);
+    }
+}
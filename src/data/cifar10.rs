@@ -0,0 +1,200 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use super::Dataset;
+use image::Image;
+use matrix::Matrix;
+
+const CHANNELS: usize = 3;
+const HEIGHT: usize = 32;
+const WIDTH: usize = 32;
+const IMAGE_SIZE: usize = CHANNELS * HEIGHT * WIDTH;
+const LABEL_SIZE: usize = 10;
+
+pub const CIFAR10_CLASSES: [&str; 10] = [
+    "airplane",
+    "automobile",
+    "bird",
+    "cat",
+    "deer",
+    "dog",
+    "frog",
+    "horse",
+    "ship",
+    "truck",
+];
+
+// Reads the binary version (i.e., the `cifar-10-batches-bin` directory).
+// Pixels are kept as bytes and converted to `f64` on access.
+#[derive(Debug)]
+pub struct Cifar10 {
+    train_images: Vec<u8>,
+    train_labels: Vec<u8>,
+    test_images: Vec<u8>,
+    test_labels: Vec<u8>,
+}
+impl Cifar10 {
+    pub fn load<P: AsRef<Path>>(data_dir: P) -> io::Result<Self> {
+        let mut train_images = Vec::new();
+        let mut train_labels = Vec::new();
+        for i in 1..=5 {
+            let path = data_dir.as_ref().join(format!("data_batch_{}.bin", i));
+            read_batch_file(&path, &mut train_images, &mut train_labels)?;
+        }
+
+        let mut test_images = Vec::new();
+        let mut test_labels = Vec::new();
+        let path = data_dir.as_ref().join("test_batch.bin");
+        read_batch_file(&path, &mut test_images, &mut test_labels)?;
+        Ok(Cifar10 {
+            train_images,
+            train_labels,
+            test_images,
+            test_labels,
+        })
+    }
+
+    pub fn train_image_count(&self) -> usize {
+        self.train_labels.len()
+    }
+
+    // image: (3, 32, 32) in RGB order
+    pub fn train_image(&self, index: usize) -> Image {
+        to_image(&self.train_images[index * IMAGE_SIZE..][..IMAGE_SIZE])
+    }
+
+    pub fn train_label(&self, index: usize) -> Vec<f64> {
+        one_hot(self.train_labels[index])
+    }
+
+    pub fn train_images(&self) -> impl Iterator<Item = Image> + '_ {
+        self.train_images.chunks(IMAGE_SIZE).map(to_image)
+    }
+
+    pub fn test_count(&self) -> usize {
+        self.test_labels.len()
+    }
+
+    pub fn test_image(&self, index: usize) -> Image {
+        to_image(&self.test_images[index * IMAGE_SIZE..][..IMAGE_SIZE])
+    }
+
+    pub fn test_label(&self, index: usize) -> Vec<f64> {
+        one_hot(self.test_labels[index])
+    }
+
+    pub fn test_images(&self) -> impl Iterator<Item = Image> + '_ {
+        self.test_images.chunks(IMAGE_SIZE).map(to_image)
+    }
+
+    // Iterates over the whole test set in order (the last batch may be smaller).
+    // Each row of the input matrix is a flattened (channel, height, width) image.
+    pub fn test_batches(&self, batch_size: usize) -> impl Iterator<Item = (Matrix, Matrix)> + '_ {
+        assert_ne!(batch_size, 0);
+        self.test_images
+            .chunks(IMAGE_SIZE * batch_size)
+            .zip(self.test_labels.chunks(batch_size))
+            .map(|(xs, ys)| {
+                let x_batch = xs.chunks(IMAGE_SIZE).map(to_vector).collect::<Vec<_>>();
+                let t_batch = ys.iter().map(|&y| one_hot(y)).collect::<Vec<_>>();
+                (Matrix::from(x_batch), Matrix::from(t_batch))
+            })
+    }
+}
+
+// The training split (inputs are flattened (channel, height, width) images)
+impl Dataset for Cifar10 {
+    fn len(&self) -> usize {
+        self.train_image_count()
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        let image = &self.train_images[index * IMAGE_SIZE..][..IMAGE_SIZE];
+        (to_vector(image), self.train_label(index))
+    }
+
+    fn label(&self, index: usize) -> Vec<f64> {
+        self.train_label(index)
+    }
+}
+
+// Each record consists of a label byte followed by 1024 red, 1024 green and 1024 blue pixels
+fn read_batch_file(path: &Path, images: &mut Vec<u8>, labels: &mut Vec<u8>) -> io::Result<()> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+
+    let invalid = |message: String| {
+        let message = format!("{} ({:?})", message, path);
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    };
+    if bytes.len() % (1 + IMAGE_SIZE) != 0 {
+        return Err(invalid(format!("Unexpected file size: {}", bytes.len())));
+    }
+    for record in bytes.chunks(1 + IMAGE_SIZE) {
+        if record[0] as usize >= LABEL_SIZE {
+            return Err(invalid(format!("Unexpected label: {}", record[0])));
+        }
+        labels.push(record[0]);
+        images.extend_from_slice(&record[1..]);
+    }
+    Ok(())
+}
+
+fn to_vector(pixels: &[u8]) -> Vec<f64> {
+    pixels.iter().map(|&v| f64::from(v) / 255.0).collect()
+}
+
+fn to_image(pixels: &[u8]) -> Image {
+    Image::from_u8(CHANNELS, HEIGHT, WIDTH, pixels)
+}
+
+fn one_hot(label: u8) -> Vec<f64> {
+    let mut v = vec![0.0; LABEL_SIZE];
+    v[label as usize] = 1.0;
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use functions::argmax;
+    use std::fs;
+    use test_util::TempDir;
+
+    #[test]
+    fn cifar10_works() {
+        let dir = TempDir::new("dlfs-cifar10");
+
+        let mut record = vec![0; 1 + IMAGE_SIZE];
+        record[0] = 3;
+        record[1 + HEIGHT * WIDTH + WIDTH + 2] = 255; // green, (y=1, x=2)
+        for i in 1..=5 {
+            fs::write(dir.join(format!("data_batch_{}.bin", i)), &record).unwrap();
+        }
+        let records = [&record[..], &record[..]].concat();
+        fs::write(dir.join("test_batch.bin"), &records).unwrap();
+
+        let cifar10 = Cifar10::load(dir.path()).unwrap();
+        assert_eq!(cifar10.train_image_count(), 5);
+        assert_eq!(cifar10.test_count(), 2);
+
+        let image = cifar10.train_image(4);
+        assert_eq!(image.channels(), 3);
+        assert_eq!(image.height(), 32);
+        assert_eq!(image.width(), 32);
+        assert_eq!(image.0[1][1][2], 1.0);
+        assert_eq!(image.0[0][1][2], 0.0);
+        assert_eq!(CIFAR10_CLASSES[argmax(&cifar10.train_label(0))], "cat");
+
+        let m = Matrix::from_images(cifar10.train_images(), 5, 5, 1, 0);
+        assert_eq!(m.shape(), (5 * 28 * 28, 3 * 5 * 5));
+
+        let batches = cifar10.test_batches(10).collect::<Vec<_>>();
+        assert_eq!(batches.len(), 1);
+        assert_eq!(batches[0].0.shape(), (2, IMAGE_SIZE));
+
+        fs::write(dir.join("test_batch.bin"), &record[1..]).unwrap();
+        assert!(Cifar10::load(dir.path()).is_err());
+    }
+}
@@ -0,0 +1,397 @@
+use rand::Rng;
+use std::io;
+use std::path::Path;
+
+use super::{Dataset, MnistOptions};
+use idx::{ElementType, IdxData, IdxReader};
+use matrix::Matrix;
+use random;
+
+const IMAGE_SIZE: usize = 28 * 28;
+const LABEL_SIZE: usize = 10;
+
+// A variant of `Mnist` that keeps the pixels as bytes (~55 MB instead of ~440 MB for all the
+// 70,000 samples) or reads them from the IDX files on demand, and converts them to `f64` on access.
+// Also works for the datasets that share the MNIST file format (i.e., Fashion-MNIST and KMNIST).
+//
+// The accessors are the same as `Mnist`'s, except that the images are returned as owned
+// vectors and wrapped in `io::Result` (the disk backend may fail to read them).
+// Labels are always read (and validated) up front, so the label accessors cannot fail.
+#[derive(Debug)]
+pub struct CompactMnist {
+    images: Images,
+
+    // One-hot
+    labels: Vec<f64>,
+    options: MnistOptions,
+}
+
+#[derive(Debug)]
+enum Images {
+    Memory(Vec<u8>),
+
+    // The image files of the training set and the test set
+    Disk([IdxReader; 2]),
+}
+
+impl CompactMnist {
+    // Reads the whole files into memory
+    pub fn load<P: AsRef<Path>>(data_dir: P, options: &MnistOptions) -> io::Result<Self> {
+        let (files, labels) = open_files(data_dir.as_ref())?;
+        let mut images = Vec::new();
+        for file in &files {
+            images.extend(read_bytes(file, 0, file.len())?);
+        }
+        Self::new(Images::Memory(images), labels, options)
+    }
+
+    // Only the headers and the labels are read here, and images are read from the files
+    // when they are accessed
+    pub fn open<P: AsRef<Path>>(data_dir: P, options: &MnistOptions) -> io::Result<Self> {
+        let (files, labels) = open_files(data_dir.as_ref())?;
+        Self::new(Images::Disk(files), labels, options)
+    }
+
+    fn new(images: Images, labels: Vec<u8>, options: &MnistOptions) -> io::Result<Self> {
+        let total = options
+            .train_size
+            .checked_add(options.validation_size)
+            .and_then(|n| n.checked_add(options.test_size));
+        if total.is_none_or(|n| n > labels.len()) {
+            let message = format!(
+                "Too many samples are requested: {:?} (there are {})",
+                options,
+                labels.len()
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        check_labels(&labels)?;
+        Ok(CompactMnist {
+            images,
+            labels: labels.into_iter().flat_map(one_hot).collect(),
+            options: options.clone(),
+        })
+    }
+
+    pub fn train_image_count(&self) -> usize {
+        self.options.train_size
+    }
+
+    pub fn train_label_count(&self) -> usize {
+        self.options.train_size
+    }
+
+    pub fn train_image(&self, index: usize) -> io::Result<Vec<f64>> {
+        assert!(index < self.train_image_count());
+        self.image(index)
+    }
+
+    pub fn train_label(&self, index: usize) -> &[f64] {
+        assert!(index < self.train_label_count());
+        self.label(index)
+    }
+
+    pub fn validation_count(&self) -> usize {
+        self.options.validation_size
+    }
+
+    pub fn validation_image(&self, index: usize) -> io::Result<Vec<f64>> {
+        assert!(index < self.validation_count());
+        self.image(self.options.train_size + index)
+    }
+
+    pub fn validation_label(&self, index: usize) -> &[f64] {
+        assert!(index < self.validation_count());
+        self.label(self.options.train_size + index)
+    }
+
+    // Iterates over the whole validation set in order (the last batch may be smaller)
+    pub fn validation_batches(
+        &self,
+        batch_size: usize,
+    ) -> impl Iterator<Item = io::Result<(Matrix, Matrix)>> + '_ {
+        self.batches(self.options.train_size, self.validation_count(), batch_size)
+    }
+
+    pub fn test_count(&self) -> usize {
+        self.options.test_size
+    }
+
+    pub fn test_image(&self, index: usize) -> io::Result<Vec<f64>> {
+        assert!(index < self.test_count());
+        self.image(self.test_offset() + index)
+    }
+
+    pub fn test_label(&self, index: usize) -> &[f64] {
+        assert!(index < self.test_count());
+        self.label(self.test_offset() + index)
+    }
+
+    pub fn test_batches(
+        &self,
+        batch_size: usize,
+    ) -> impl Iterator<Item = io::Result<(Matrix, Matrix)>> + '_ {
+        self.batches(self.test_offset(), self.test_count(), batch_size)
+    }
+
+    pub fn choice_train_batch(
+        &self,
+        batch_size: usize,
+    ) -> impl Iterator<Item = io::Result<(Vec<f64>, Vec<f64>)>> + '_ {
+        (0..batch_size).map(move |_| {
+            let i = random::with_rng(|rng| rng.gen_range(0, self.train_image_count()));
+            Ok((self.train_image(i)?, Vec::from(self.train_label(i))))
+        })
+    }
+
+    // Prefer `DataLoader`, which visits every sample once per epoch
+    pub fn choice_train_batch2(&self, batch_size: usize) -> io::Result<(Matrix, Matrix)> {
+        let mut x_batch = Vec::with_capacity(batch_size);
+        let mut t_batch = Vec::with_capacity(batch_size);
+        for sample in self.choice_train_batch(batch_size) {
+            let (x, t) = sample?;
+            x_batch.push(x);
+            t_batch.push(t);
+        }
+        Ok((Matrix::from(x_batch), Matrix::from(t_batch)))
+    }
+
+    fn test_offset(&self) -> usize {
+        self.options.train_size + self.options.validation_size
+    }
+
+    fn image(&self, index: usize) -> io::Result<Vec<f64>> {
+        Ok(to_f64(&self.read_images(index, 1)?))
+    }
+
+    fn label(&self, index: usize) -> &[f64] {
+        &self.labels[index * LABEL_SIZE..][..LABEL_SIZE]
+    }
+
+    fn batches(
+        &self,
+        offset: usize,
+        count: usize,
+        batch_size: usize,
+    ) -> impl Iterator<Item = io::Result<(Matrix, Matrix)>> + '_ {
+        assert_ne!(batch_size, 0);
+        (0..count).step_by(batch_size).map(move |start| {
+            let n = batch_size.min(count - start);
+            let images = self.read_images(offset + start, n)?;
+            let x = images.chunks(IMAGE_SIZE).map(to_f64).collect::<Vec<_>>();
+            let t = self.labels[(offset + start) * LABEL_SIZE..][..n * LABEL_SIZE]
+                .chunks(LABEL_SIZE)
+                .map(Vec::from)
+                .collect::<Vec<_>>();
+            Ok((Matrix::from(x), Matrix::from(t)))
+        })
+    }
+
+    // Returns the pixels of the samples in `[start, start + count)`.
+    // The sample indices run through the training files and then the test files.
+    fn read_images(&self, start: usize, count: usize) -> io::Result<Vec<u8>> {
+        match self.images {
+            Images::Memory(ref images) => {
+                Ok(images[start * IMAGE_SIZE..][..count * IMAGE_SIZE].to_vec())
+            }
+            Images::Disk(ref files) => {
+                let mut images = Vec::with_capacity(count * IMAGE_SIZE);
+                let (mut start, mut count) = (start, count);
+                for file in files {
+                    let n = file.len();
+                    if start >= n {
+                        start -= n;
+                        continue;
+                    }
+                    let m = count.min(n - start);
+                    images.extend(read_bytes(file, start, m)?);
+                    start = 0;
+                    count -= m;
+                    if count == 0 {
+                        break;
+                    }
+                }
+                Ok(images)
+            }
+        }
+    }
+}
+
+// The training split.
+// `Dataset` cannot report errors, so I/O errors of the disk backend panic here;
+// use `train_image` to handle them.
+impl Dataset for CompactMnist {
+    fn len(&self) -> usize {
+        self.train_image_count()
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        let image = self
+            .train_image(index)
+            .unwrap_or_else(|e| panic!("Failed to read MNIST image {}: {}", index, e));
+        (image, Vec::from(self.train_label(index)))
+    }
+
+    fn label(&self, index: usize) -> Vec<f64> {
+        Vec::from(self.train_label(index))
+    }
+}
+
+// Returns the image files of the training and test sets, and all the labels
+fn open_files(data_dir: &Path) -> io::Result<([IdxReader; 2], Vec<u8>)> {
+    let mut labels = Vec::new();
+    let mut open = |prefix: &str| -> io::Result<IdxReader> {
+        let images = IdxReader::open(data_dir.join(format!("{}-images-idx3-ubyte", prefix)))?;
+        let label_file = IdxReader::open(data_dir.join(format!("{}-labels-idx1-ubyte", prefix)))?;
+        let types = (images.element_type(), label_file.element_type());
+        if images.dims() != [label_file.len(), 28, 28]
+            || label_file.dims().len() != 1
+            || types != (ElementType::U8, ElementType::U8)
+        {
+            let message = format!(
+                "Unexpected MNIST files: images={:?} ({:?}), labels={:?} ({:?})",
+                images.dims(),
+                types.0,
+                label_file.dims(),
+                types.1
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        labels.extend(read_bytes(&label_file, 0, label_file.len())?);
+        Ok(images)
+    };
+    let files = [open("train")?, open("t10k")?];
+    Ok((files, labels))
+}
+
+fn read_bytes(reader: &IdxReader, start: usize, count: usize) -> io::Result<Vec<u8>> {
+    match reader.read_items(start, count)? {
+        IdxData::U8(bytes) => Ok(bytes),
+        _ => unreachable!(),
+    }
+}
+
+fn check_labels(labels: &[u8]) -> io::Result<()> {
+    if let Some(&label) = labels.iter().find(|&&l| l as usize >= LABEL_SIZE) {
+        let message = format!("Unexpected MNIST label: {}", label);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    Ok(())
+}
+
+fn to_f64(pixels: &[u8]) -> Vec<f64> {
+    pixels.iter().map(|&v| f64::from(v) / 255.0).collect()
+}
+
+fn one_hot(label: u8) -> Vec<f64> {
+    let mut t = vec![0.0; LABEL_SIZE];
+    t[label as usize] = 1.0;
+    t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use idx::Idx;
+    use std::fs;
+    use std::thread;
+    use test_util::TempDir;
+
+    #[test]
+    fn compact_mnist_works() {
+        let dir = TempDir::new("dlfs-compact");
+        for &(prefix, n) in &[("train", 3), ("t10k", 2)] {
+            let offset = if prefix == "train" { 0 } else { 3 };
+            let images = (0..n)
+                .flat_map(|i| vec![(offset + i) as u8 * 51; IMAGE_SIZE])
+                .collect();
+            let labels = (0..n).map(|i| (offset + i) as u8).collect();
+            Idx::new(vec![n, 28, 28], IdxData::U8(images))
+                .unwrap()
+                .save(dir.join(format!("{}-images-idx3-ubyte", prefix)))
+                .unwrap();
+            Idx::new(vec![n], IdxData::U8(labels))
+                .unwrap()
+                .save(dir.join(format!("{}-labels-idx1-ubyte", prefix)))
+                .unwrap();
+        }
+
+        // The validation set spans both the training and test files
+        let options = MnistOptions {
+            train_size: 2,
+            validation_size: 2,
+            test_size: 1,
+        };
+        for mnist in &[
+            CompactMnist::load(dir.path(), &options).unwrap(),
+            CompactMnist::open(dir.path(), &options).unwrap(),
+        ] {
+            assert_eq!(mnist.len(), 2);
+            assert_eq!(mnist.train_image(1).unwrap()[0], 0.2);
+            assert_eq!(mnist.train_label(1)[1], 1.0);
+            assert_eq!(mnist.validation_count(), 2);
+            assert_eq!(mnist.validation_image(1).unwrap()[783], 0.6);
+            assert_eq!(mnist.test_label(0)[4], 1.0);
+
+            let batches = mnist
+                .validation_batches(3)
+                .collect::<io::Result<Vec<_>>>()
+                .unwrap();
+            assert_eq!(batches.len(), 1);
+            assert_eq!(batches[0].0.shape(), (2, IMAGE_SIZE));
+            assert_eq!(batches[0].1.row_slice(0)[2], 1.0);
+            assert_eq!(batches[0].1.row_slice(1)[3], 1.0);
+            assert!(mnist.choice_train_batch(4).all(|s| s.is_ok()));
+            // Readers are shared across threads (e.g., by `PrefetchLoader` workers)
+            thread::scope(|scope| {
+                for i in 0..2 {
+                    scope.spawn(move || {
+                        assert_eq!(mnist.train_image(i).unwrap()[0], i as f64 * 0.2)
+                    });
+                }
+            });
+            let (x, t) = mnist.choice_train_batch2(4).unwrap();
+            assert_eq!((x.shape(), t.shape()), ((4, IMAGE_SIZE), (4, LABEL_SIZE)));
+        }
+
+        let options = MnistOptions {
+            train_size: 5,
+            validation_size: 1,
+            test_size: 0,
+        };
+        assert!(CompactMnist::open(dir.path(), &options).is_err());
+        let options = MnistOptions {
+            train_size: usize::MAX,
+            validation_size: 2,
+            test_size: 0,
+        };
+        assert!(CompactMnist::open(dir.path(), &options).is_err());
+
+        // I/O errors of the disk backend are returned from the accessors
+        let options = MnistOptions {
+            train_size: 3,
+            validation_size: 0,
+            test_size: 0,
+        };
+        let mnist = CompactMnist::open(dir.path(), &options).unwrap();
+        fs::OpenOptions::new()
+            .write(true)
+            .open(dir.join("train-images-idx3-ubyte"))
+            .unwrap()
+            .set_len(100)
+            .unwrap();
+        assert!(mnist.train_image(2).is_err());
+
+        // Invalid labels are reported when the files are opened
+        Idx::new(vec![2], IdxData::U8(vec![3, 10]))
+            .unwrap()
+            .save(dir.join("t10k-labels-idx1-ubyte"))
+            .unwrap();
+        let options = MnistOptions {
+            train_size: 1,
+            validation_size: 0,
+            test_size: 0,
+        };
+        assert!(CompactMnist::open(dir.path(), &options).is_err());
+    }
+}
@@ -0,0 +1,512 @@
+use rand::seq::SliceRandom;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use super::MatrixDataset;
+use matrix::Matrix;
+use random;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingValue {
+    // Fills missing numeric fields with the mean of the train split
+    Mean,
+    Zero,
+    // Removes rows that have any missing feature (or a missing label).
+    // With the other strategies, missing labels are errors.
+    DropRow,
+}
+
+// Columns are specified by their (zero-based) positions in the file
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub delimiter: char,
+    pub has_header: bool,
+    pub label_column: usize,
+
+    // One-hot encoded features (the other columns must be numeric)
+    pub categorical_columns: Vec<usize>,
+    pub ignored_columns: Vec<usize>,
+
+    // Fields equal to one of these strings (after trimming) are treated as missing
+    pub missing_markers: Vec<String>,
+    pub missing_value: MissingValue,
+
+    // Standardizes numeric features to zero mean and unit variance
+    pub standardize: bool,
+}
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: ',',
+            has_header: true,
+            label_column: 0,
+            categorical_columns: Vec::new(),
+            ignored_columns: Vec::new(),
+            missing_markers: vec![String::new(), "NA".to_owned(), "?".to_owned()],
+            missing_value: MissingValue::Mean,
+            standardize: true,
+        }
+    }
+}
+
+// The raw (i.e., not yet encoded) fields of a CSV file
+#[derive(Debug, Clone)]
+pub struct CsvTable {
+    header: Option<Vec<String>>,
+    rows: Vec<Vec<String>>,
+}
+impl CsvTable {
+    pub fn load<P: AsRef<Path>>(path: P, options: &CsvOptions) -> io::Result<Self> {
+        let file = File::open(path)?;
+        Self::read_from(file, options)
+    }
+
+    pub fn read_from<R: Read>(reader: R, options: &CsvOptions) -> io::Result<Self> {
+        let mut header = None;
+        let mut rows = Vec::new();
+        for (i, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let fields = split_fields(&line, options.delimiter);
+            if options.has_header && header.is_none() && rows.is_empty() {
+                header = Some(fields);
+                continue;
+            }
+            let columns = header.as_ref().or_else(|| rows.first()).map(Vec::len);
+            if columns.is_some_and(|n| n != fields.len()) {
+                let message = format!(
+                    "Line {}: expected {} fields, but got {}",
+                    i + 1,
+                    columns.unwrap_or(0),
+                    fields.len()
+                );
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+            rows.push(fields);
+        }
+        Ok(CsvTable { header, rows })
+    }
+
+    pub fn header(&self) -> Option<&[String]> {
+        self.header.as_ref().map(|h| &h[..])
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn columns(&self) -> usize {
+        self.header
+            .as_ref()
+            .or_else(|| self.rows.first())
+            .map_or(0, Vec::len)
+    }
+
+    pub fn shuffle(&mut self) {
+        random::with_rng(|rng| self.rows.shuffle(rng));
+    }
+
+    // Splits the rows into `[0, index)` and `[index, rows)`.
+    // `index` is clamped to `rows`, so the second table is empty if `index >= rows`.
+    pub fn split_at(mut self, index: usize) -> (Self, Self) {
+        let index = index.min(self.rows.len());
+        let rest = self.rows.split_off(index);
+        let other = CsvTable {
+            header: self.header.clone(),
+            rows: rest,
+        };
+        (self, other)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ColumnEncoder {
+    Numeric {
+        column: usize,
+        mean: f64,
+        std: f64,
+    },
+    Categorical {
+        column: usize,
+        categories: Vec<String>,
+    },
+}
+
+// Converts `CsvTable`s into `MatrixDataset`s.
+// All statistics (categories, means and standard deviations) come from the table given to `fit`,
+// so fit it on the train split and reuse it for the validation and test splits.
+#[derive(Debug, Clone)]
+pub struct TabularEncoder {
+    options: CsvOptions,
+    columns: usize,
+    encoders: Vec<ColumnEncoder>,
+    classes: Vec<String>,
+}
+impl TabularEncoder {
+    pub fn fit(table: &CsvTable, options: &CsvOptions) -> io::Result<Self> {
+        let columns = table.columns();
+        if options.label_column >= columns {
+            let message = format!(
+                "Label column {} is out of range (columns={})",
+                options.label_column, columns
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+
+        let mut encoder = TabularEncoder {
+            options: options.clone(),
+            columns,
+            encoders: Vec::new(),
+            classes: Vec::new(),
+        };
+        let rows = table
+            .rows
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| {
+                options.missing_value != MissingValue::DropRow || !encoder.has_missing(row)
+            })
+            .collect::<Vec<_>>();
+
+        for column in 0..columns {
+            if column == options.label_column || options.ignored_columns.contains(&column) {
+                continue;
+            }
+            if options.categorical_columns.contains(&column) {
+                let categories = unique(rows.iter().map(|(_, row)| &row[column][..]), |f| {
+                    !encoder.is_missing(f)
+                });
+                encoder
+                    .encoders
+                    .push(ColumnEncoder::Categorical { column, categories });
+                continue;
+            }
+
+            let mut values = Vec::with_capacity(rows.len());
+            for &(i, row) in &rows {
+                if let Some(v) = encoder.parse_numeric(i, column, &row[column])? {
+                    values.push(v);
+                }
+            }
+            let n = values.len().max(1) as f64;
+            let mean = values.iter().sum::<f64>() / n;
+            let std = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+            encoder.encoders.push(ColumnEncoder::Numeric {
+                column,
+                mean,
+                std: if std > 0.0 { std } else { 1.0 },
+            });
+        }
+
+        for &(i, row) in &rows {
+            encoder.check_label(i, row)?;
+        }
+        encoder.classes = unique(
+            rows.iter().map(|(_, row)| &row[options.label_column][..]),
+            |_| true,
+        );
+        Ok(encoder)
+    }
+
+    // The number of columns of the encoded inputs
+    pub fn feature_size(&self) -> usize {
+        self.encoders
+            .iter()
+            .map(|e| match *e {
+                ColumnEncoder::Numeric { .. } => 1,
+                ColumnEncoder::Categorical { ref categories, .. } => categories.len(),
+            })
+            .sum()
+    }
+
+    // Label values in the order of the one-hot columns
+    pub fn classes(&self) -> &[String] {
+        &self.classes
+    }
+
+    // Unknown categories of features are encoded as all zeros, but unknown labels are errors
+    pub fn encode(&self, table: &CsvTable) -> io::Result<MatrixDataset> {
+        let mut xs = Vec::with_capacity(table.rows());
+        let mut ts = Vec::with_capacity(table.rows());
+        for (i, row) in table.rows.iter().enumerate() {
+            if row.len() != self.columns {
+                let message = format!(
+                    "Row {}: expected {} fields, but got {}",
+                    i,
+                    self.columns,
+                    row.len()
+                );
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+            if self.options.missing_value == MissingValue::DropRow && self.has_missing(row) {
+                continue;
+            }
+
+            let mut x = Vec::with_capacity(self.feature_size());
+            for e in &self.encoders {
+                match *e {
+                    ColumnEncoder::Numeric { column, mean, std } => {
+                        let v = match self.parse_numeric(i, column, &row[column])? {
+                            Some(v) => v,
+                            None if self.options.missing_value == MissingValue::Zero => 0.0,
+                            None => mean,
+                        };
+                        x.push(if self.options.standardize {
+                            (v - mean) / std
+                        } else {
+                            v
+                        });
+                    }
+                    ColumnEncoder::Categorical {
+                        column,
+                        ref categories,
+                    } => {
+                        let field = &row[column];
+                        x.extend(
+                            categories
+                                .iter()
+                                .map(|c| if c == field { 1.0 } else { 0.0 }),
+                        );
+                    }
+                }
+            }
+
+            self.check_label(i, row)?;
+            let label = &row[self.options.label_column];
+            let class = self.classes.iter().position(|c| c == label);
+            let class = class.ok_or_else(|| {
+                let message = format!("Row {}: unknown label {:?}", i, label);
+                io::Error::new(io::ErrorKind::InvalidData, message)
+            })?;
+            let mut t = vec![0.0; self.classes.len()];
+            t[class] = 1.0;
+
+            xs.push(x);
+            ts.push(t);
+        }
+        Ok(MatrixDataset::new(Matrix::from(xs), Matrix::from(ts)))
+    }
+
+    fn is_missing(&self, field: &str) -> bool {
+        let field = field.trim();
+        self.options.missing_markers.iter().any(|m| m == field)
+    }
+
+    // Including the label
+    fn has_missing(&self, row: &[String]) -> bool {
+        row.iter().enumerate().any(|(column, field)| {
+            !self.options.ignored_columns.contains(&column) && self.is_missing(field)
+        })
+    }
+
+    fn check_label(&self, row: usize, fields: &[String]) -> io::Result<()> {
+        let label = &fields[self.options.label_column];
+        if self.is_missing(label) {
+            let message = format!("Row {}: missing label {:?}", row, label);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        Ok(())
+    }
+
+    fn parse_numeric(&self, row: usize, column: usize, field: &str) -> io::Result<Option<f64>> {
+        if self.is_missing(field) {
+            return Ok(None);
+        }
+        field.trim().parse().map(Some).map_err(|_| {
+            let message = format!(
+                "Row {}, column {}: {:?} is not a number (mark the column as categorical?)",
+                row, column, field
+            );
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        })
+    }
+}
+
+// Distinct fields in sorted order (`split_fields` has already trimmed the unquoted ones)
+fn unique<'a, I, F>(fields: I, filter: F) -> Vec<String>
+where
+    I: Iterator<Item = &'a str>,
+    F: Fn(&str) -> bool,
+{
+    let mut values = fields
+        .filter(|f| filter(f))
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    values.sort();
+    values.dedup();
+    values
+}
+
+// Fields may be quoted with `"` (and `""` in a quoted field means a literal `"`).
+// Unquoted fields are trimmed, but the spaces inside quotes are kept.
+fn split_fields(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+
+    // The length of `field` at the closing quote (the spaces inside quotes are kept)
+    let mut quoted_len = None;
+    let mut chars = line.trim_end_matches('\r').chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            if c != '"' {
+                field.push(c);
+            } else if chars.peek() == Some(&'"') {
+                field.push('"');
+                chars.next();
+            } else {
+                quoted = false;
+                quoted_len = Some(field.len());
+            }
+        } else if c == '"' {
+            if field.trim().is_empty() {
+                field.clear();
+            }
+            quoted = true;
+        } else if c == delimiter {
+            fields.push(finish_field(&field, quoted_len.take()));
+            field.clear();
+        } else {
+            field.push(c);
+        }
+    }
+    fields.push(finish_field(&field, quoted_len));
+    fields
+}
+
+fn finish_field(field: &str, quoted_len: Option<usize>) -> String {
+    match quoted_len {
+        None => field.trim().to_owned(),
+        Some(n) => format!("{}{}", &field[..n], field[n..].trim_end()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data::Dataset;
+
+    const CSV: &str = "\
+color,size,weight,label
+red,1.0,10,a
+blue,2.0,,b
+\"red\",3.0,30,a
+green,NA,20,b
+";
+
+    fn options() -> CsvOptions {
+        CsvOptions {
+            label_column: 3,
+            categorical_columns: vec![0],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn split_fields_works() {
+        assert_eq!(split_fields("a, b ,c", ','), ["a", "b", "c"]);
+        assert_eq!(
+            split_fields("\"x,y\",\"say \"\"hi\"\"\",", ','),
+            ["x,y", "say \"hi\"", ""]
+        );
+        assert_eq!(split_fields("1\t2", '\t'), ["1", "2"]);
+
+        // Spaces inside quotes are part of the value
+        assert_eq!(split_fields(" \" a \" ,\"b \",c", ','), [" a ", "b ", "c"]);
+    }
+
+    #[test]
+    fn encode_works() {
+        let table = CsvTable::read_from(CSV.as_bytes(), &options()).unwrap();
+        assert_eq!(table.header().unwrap()[3], "label");
+        assert_eq!((table.rows(), table.columns()), (4, 4));
+
+        let mut options = options();
+        options.standardize = false;
+        let encoder = TabularEncoder::fit(&table, &options).unwrap();
+        assert_eq!(encoder.feature_size(), 5);
+        assert_eq!(encoder.classes(), ["a", "b"]);
+
+        // color=(blue, green, red), size, weight (missing values are filled with means)
+        let dataset = encoder.encode(&table).unwrap();
+        assert_eq!(dataset.len(), 4);
+        assert_eq!(
+            dataset.get(1),
+            (vec![1.0, 0.0, 0.0, 2.0, 20.0], vec![0.0, 1.0])
+        );
+        assert_eq!(
+            dataset.get(3),
+            (vec![0.0, 1.0, 0.0, 2.0, 20.0], vec![0.0, 1.0])
+        );
+
+        options.missing_value = MissingValue::DropRow;
+        let encoder = TabularEncoder::fit(&table, &options).unwrap();
+        let dataset = encoder.encode(&table).unwrap();
+        assert_eq!(dataset.len(), 2);
+        assert_eq!(dataset.get(1), (vec![1.0, 3.0, 30.0], vec![1.0]));
+    }
+
+    #[test]
+    fn statistics_come_from_train_split() {
+        let table = CsvTable::read_from(CSV.as_bytes(), &options()).unwrap();
+        let (train, test) = table.split_at(2);
+        let encoder = TabularEncoder::fit(&train, &options()).unwrap();
+
+        // size: mean=1.5, std=0.5
+        let train = encoder.encode(&train).unwrap();
+        assert_eq!(
+            train.x().column(2).cloned().collect::<Vec<_>>(),
+            [-1.0, 1.0]
+        );
+        let test = encoder.encode(&test).unwrap();
+        assert_eq!(test.x().column(2).cloned().collect::<Vec<_>>(), [3.0, 0.0]);
+
+        // "green" is unknown to the encoder
+        assert_eq!(&test.x().row_slice(1)[..2], [0.0, 0.0]);
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        let ragged = "a,b\n1,2\n3\n";
+        assert!(CsvTable::read_from(ragged.as_bytes(), &Default::default()).is_err());
+
+        let table = CsvTable::read_from(CSV.as_bytes(), &Default::default()).unwrap();
+        let options = CsvOptions {
+            label_column: 3,
+            ..Default::default()
+        };
+        assert!(TabularEncoder::fit(&table, &options).is_err());
+
+        let (train, test) = CsvTable::read_from("x,y\n1,a\n2,b\n".as_bytes(), &Default::default())
+            .unwrap()
+            .split_at(1);
+        let options = CsvOptions {
+            label_column: 1,
+            ..Default::default()
+        };
+        let encoder = TabularEncoder::fit(&train, &options).unwrap();
+        assert!(encoder.encode(&test).is_err());
+    }
+
+    #[test]
+    fn missing_labels_work() {
+        let csv = "x,y\n1,a\n2,\n3,NA\n4,b\n";
+        let table = CsvTable::read_from(csv.as_bytes(), &Default::default()).unwrap();
+        let mut options = CsvOptions {
+            label_column: 1,
+            ..Default::default()
+        };
+        assert!(TabularEncoder::fit(&table, &options).is_err());
+
+        options.missing_value = MissingValue::DropRow;
+        let encoder = TabularEncoder::fit(&table, &options).unwrap();
+        assert_eq!(encoder.classes(), ["a", "b"]);
+        assert_eq!(encoder.encode(&table).unwrap().len(), 2);
+
+        let (all, rest) = table.split_at(10);
+        assert_eq!((all.rows(), rest.rows()), (4, 0));
+    }
+}
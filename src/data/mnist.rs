@@ -0,0 +1,448 @@
+use rand::Rng;
+use std::io;
+use std::path::Path;
+
+use super::{batches, Dataset};
+use idx::{Idx, IdxData};
+use matrix::Matrix;
+use random;
+
+const IMAGE_SIZE: usize = 28 * 28;
+const LABEL_SIZE: usize = 10;
+
+// The train, validation and test sets are carved out of the 70,000 MNIST samples in this order
+// (i.e., the validation set is taken from the tail of the original training data).
+#[derive(Debug, Clone)]
+pub struct MnistOptions {
+    pub train_size: usize,
+    pub validation_size: usize,
+    pub test_size: usize,
+}
+impl Default for MnistOptions {
+    fn default() -> Self {
+        MnistOptions {
+            train_size: 60_000,
+            validation_size: 0,
+            test_size: 10_000,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Mnist {
+    x_train: Vec<f64>,
+    y_train: Vec<f64>,
+    x_validation: Vec<f64>,
+    y_validation: Vec<f64>,
+    x_test: Vec<f64>,
+    y_test: Vec<f64>,
+}
+impl Mnist {
+    pub fn load<P: AsRef<Path>>(data_dir: P) -> Self {
+        Self::load_with_options(data_dir, &Default::default())
+    }
+
+    pub fn load_with_options<P: AsRef<Path>>(data_dir: P, options: &MnistOptions) -> Self {
+        let mnist = ::mnist::MnistBuilder::new()
+            .base_path(data_dir.as_ref().to_str().expect("Wrong path"))
+            .label_format_one_hot()
+            .training_set_length(options.train_size as u32)
+            .validation_set_length(options.validation_size as u32)
+            .test_set_length(options.test_size as u32)
+            .finalize();
+        let images = |xs: Vec<u8>| xs.into_iter().map(|v| (v as f64) / 255.0).collect();
+        let labels = |xs: Vec<u8>| xs.into_iter().map(|v| v as f64).collect();
+        Mnist {
+            x_train: images(mnist.trn_img),
+            y_train: labels(mnist.trn_lbl),
+            x_validation: images(mnist.val_img),
+            y_validation: labels(mnist.val_lbl),
+            x_test: images(mnist.tst_img),
+            y_test: labels(mnist.tst_lbl),
+        }
+    }
+
+    pub fn train_image_count(&self) -> usize {
+        self.x_train.len() / IMAGE_SIZE
+    }
+
+    pub fn train_label_count(&self) -> usize {
+        self.y_train.len() / LABEL_SIZE
+    }
+
+    pub fn train_image(&self, index: usize) -> &[f64] {
+        &self.x_train[index * IMAGE_SIZE..][..IMAGE_SIZE]
+    }
+
+    pub fn train_label(&self, index: usize) -> &[f64] {
+        &self.y_train[index * LABEL_SIZE..][..LABEL_SIZE]
+    }
+
+    pub fn validation_count(&self) -> usize {
+        self.y_validation.len() / LABEL_SIZE
+    }
+
+    pub fn validation_image(&self, index: usize) -> &[f64] {
+        &self.x_validation[index * IMAGE_SIZE..][..IMAGE_SIZE]
+    }
+
+    pub fn validation_label(&self, index: usize) -> &[f64] {
+        &self.y_validation[index * LABEL_SIZE..][..LABEL_SIZE]
+    }
+
+    pub fn validation_batches(
+        &self,
+        batch_size: usize,
+    ) -> impl Iterator<Item = (Matrix, Matrix)> + '_ {
+        batches(
+            &self.x_validation,
+            &self.y_validation,
+            IMAGE_SIZE,
+            LABEL_SIZE,
+            batch_size,
+        )
+    }
+
+    pub fn test_count(&self) -> usize {
+        self.y_test.len() / LABEL_SIZE
+    }
+
+    pub fn test_image(&self, index: usize) -> &[f64] {
+        &self.x_test[index * IMAGE_SIZE..][..IMAGE_SIZE]
+    }
+
+    pub fn test_label(&self, index: usize) -> &[f64] {
+        &self.y_test[index * LABEL_SIZE..][..LABEL_SIZE]
+    }
+
+    // Iterates over the whole test set in order (the last batch may be smaller)
+    pub fn test_batches(&self, batch_size: usize) -> impl Iterator<Item = (Matrix, Matrix)> + '_ {
+        batches(
+            &self.x_test,
+            &self.y_test,
+            IMAGE_SIZE,
+            LABEL_SIZE,
+            batch_size,
+        )
+    }
+
+    pub fn choice_train_batch(&self, batch_size: usize) -> impl Iterator<Item = MnistEntry<'_>> {
+        (0..batch_size).map(move |_| {
+            let i = random::with_rng(|rng| rng.gen_range(0, self.train_image_count()));
+            MnistEntry {
+                image: self.train_image(i),
+                label: self.train_label(i),
+            }
+        })
+    }
+
+    // Samples with replacement like `choice_train_batch`.
+    // Kept for the code written before `DataLoader`, which visits every sample once per epoch.
+    pub fn choice_train_batch2(&self, batch_size: usize) -> (Matrix, Matrix) {
+        entries_to_batch(self.choice_train_batch(batch_size))
+    }
+}
+
+// The training split
+impl Dataset for Mnist {
+    fn len(&self) -> usize {
+        self.train_image_count()
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        (
+            Vec::from(self.train_image(index)),
+            Vec::from(self.train_label(index)),
+        )
+    }
+
+    fn label(&self, index: usize) -> Vec<f64> {
+        Vec::from(self.train_label(index))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MnistEntry<'a> {
+    pub image: &'a [f64],
+    pub label: &'a [f64],
+}
+
+fn entries_to_batch<'a, I>(entries: I) -> (Matrix, Matrix)
+where
+    I: Iterator<Item = MnistEntry<'a>>,
+{
+    let (x_batch, t_batch): (Vec<_>, Vec<_>) = entries
+        .map(|e| (Vec::from(e.image), Vec::from(e.label)))
+        .unzip();
+    (Matrix::from(x_batch), Matrix::from(t_batch))
+}
+
+// Fashion-MNIST and KMNIST are distributed as drop-in replacements for MNIST
+// (same file names, image size and number of classes)
+pub type FashionMnist = Mnist;
+pub type Kmnist = Mnist;
+
+pub const FASHION_MNIST_CLASSES: [&str; 10] = [
+    "T-shirt/top",
+    "Trouser",
+    "Pullover",
+    "Dress",
+    "Coat",
+    "Sandal",
+    "Shirt",
+    "Sneaker",
+    "Bag",
+    "Ankle boot",
+];
+
+pub const KMNIST_CLASSES: [&str; 10] = ["お", "き", "す", "つ", "な", "は", "ま", "や", "れ", "を"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmnistSplit {
+    ByClass,
+    ByMerge,
+    Balanced,
+    Letters,
+    Digits,
+    Mnist,
+}
+impl EmnistSplit {
+    pub fn classes(self) -> usize {
+        match self {
+            EmnistSplit::ByClass => 62,
+            EmnistSplit::ByMerge | EmnistSplit::Balanced => 47,
+            EmnistSplit::Letters => 26,
+            EmnistSplit::Digits | EmnistSplit::Mnist => 10,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            EmnistSplit::ByClass => "byclass",
+            EmnistSplit::ByMerge => "bymerge",
+            EmnistSplit::Balanced => "balanced",
+            EmnistSplit::Letters => "letters",
+            EmnistSplit::Digits => "digits",
+            EmnistSplit::Mnist => "mnist",
+        }
+    }
+}
+
+// Reads the (uncompressed) files named like `emnist-balanced-train-images-idx3-ubyte`
+#[derive(Debug)]
+pub struct Emnist {
+    split: EmnistSplit,
+    x_train: Vec<f64>,
+    y_train: Vec<f64>,
+    x_test: Vec<f64>,
+    y_test: Vec<f64>,
+}
+impl Emnist {
+    pub fn load<P: AsRef<Path>>(data_dir: P, split: EmnistSplit) -> io::Result<Self> {
+        let path = |kind| {
+            let name = format!("emnist-{}-{}", split.name(), kind);
+            data_dir.as_ref().join(name)
+        };
+        let (x_train, y_train) = load_emnist_set(
+            &path("train-images-idx3-ubyte"),
+            &path("train-labels-idx1-ubyte"),
+            split,
+        )?;
+        let (x_test, y_test) = load_emnist_set(
+            &path("test-images-idx3-ubyte"),
+            &path("test-labels-idx1-ubyte"),
+            split,
+        )?;
+        Ok(Emnist {
+            split,
+            x_train,
+            y_train,
+            x_test,
+            y_test,
+        })
+    }
+
+    pub fn split(&self) -> EmnistSplit {
+        self.split
+    }
+
+    pub fn classes(&self) -> usize {
+        self.split.classes()
+    }
+
+    pub fn train_image_count(&self) -> usize {
+        self.x_train.len() / IMAGE_SIZE
+    }
+
+    pub fn train_label_count(&self) -> usize {
+        self.y_train.len() / self.classes()
+    }
+
+    pub fn train_image(&self, index: usize) -> &[f64] {
+        &self.x_train[index * IMAGE_SIZE..][..IMAGE_SIZE]
+    }
+
+    pub fn train_label(&self, index: usize) -> &[f64] {
+        let n = self.classes();
+        &self.y_train[index * n..][..n]
+    }
+
+    pub fn test_count(&self) -> usize {
+        self.x_test.len() / IMAGE_SIZE
+    }
+
+    pub fn test_image(&self, index: usize) -> &[f64] {
+        &self.x_test[index * IMAGE_SIZE..][..IMAGE_SIZE]
+    }
+
+    pub fn test_label(&self, index: usize) -> &[f64] {
+        let n = self.classes();
+        &self.y_test[index * n..][..n]
+    }
+
+    // Iterates over the whole test set in order (the last batch may be smaller)
+    pub fn test_batches(&self, batch_size: usize) -> impl Iterator<Item = (Matrix, Matrix)> + '_ {
+        batches(
+            &self.x_test,
+            &self.y_test,
+            IMAGE_SIZE,
+            self.classes(),
+            batch_size,
+        )
+    }
+
+    pub fn choice_train_batch(&self, batch_size: usize) -> impl Iterator<Item = MnistEntry<'_>> {
+        (0..batch_size).map(move |_| {
+            let i = random::with_rng(|rng| rng.gen_range(0, self.train_image_count()));
+            MnistEntry {
+                image: self.train_image(i),
+                label: self.train_label(i),
+            }
+        })
+    }
+
+    // Samples with replacement like `choice_train_batch`.
+    // Kept for the code written before `DataLoader`, which visits every sample once per epoch.
+    pub fn choice_train_batch2(&self, batch_size: usize) -> (Matrix, Matrix) {
+        entries_to_batch(self.choice_train_batch(batch_size))
+    }
+}
+
+// The training split
+impl Dataset for Emnist {
+    fn len(&self) -> usize {
+        self.train_image_count()
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        (
+            Vec::from(self.train_image(index)),
+            Vec::from(self.train_label(index)),
+        )
+    }
+
+    fn label(&self, index: usize) -> Vec<f64> {
+        Vec::from(self.train_label(index))
+    }
+}
+
+fn load_emnist_set(
+    images_path: &Path,
+    labels_path: &Path,
+    split: EmnistSplit,
+) -> io::Result<(Vec<f64>, Vec<f64>)> {
+    let (image_dims, images) = read_idx_u8(images_path)?;
+    let (label_dims, labels) = read_idx_u8(labels_path)?;
+    if label_dims.len() != 1 || image_dims != [label_dims[0], 28, 28] {
+        let message = format!(
+            "Unexpected EMNIST shapes: images={:?}, labels={:?}",
+            image_dims, label_dims
+        );
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+
+    // EMNIST images are stored transposed (i.e., column-major)
+    let mut xs = Vec::with_capacity(images.len());
+    for image in images.chunks(IMAGE_SIZE) {
+        for y in 0..28 {
+            for x in 0..28 {
+                xs.push(f64::from(image[x * 28 + y]) / 255.0);
+            }
+        }
+    }
+
+    // The labels of the letters split start from 1 ('a' or 'A')
+    let offset = if split == EmnistSplit::Letters { 1 } else { 0 };
+    let classes = split.classes();
+    let mut ys = vec![0.0; labels.len() * classes];
+    for (i, &label) in labels.iter().enumerate() {
+        let label = (label as usize).wrapping_sub(offset);
+        if label >= classes {
+            let message = format!("Unexpected EMNIST label: {}", label.wrapping_add(offset));
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        ys[i * classes + label] = 1.0;
+    }
+    Ok((xs, ys))
+}
+
+// Reads an IDX file whose elements are unsigned bytes
+fn read_idx_u8(path: &Path) -> io::Result<(Vec<usize>, Vec<u8>)> {
+    let idx = Idx::open(path)?;
+    let dims = idx.dims().to_vec();
+    match idx.into_data() {
+        IdxData::U8(data) => Ok((dims, data)),
+        data => {
+            let message = format!(
+                "Expected unsigned bytes, but got {:?} ({:?})",
+                data.element_type(),
+                path
+            );
+            Err(io::Error::new(io::ErrorKind::InvalidData, message))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::TempDir;
+
+    fn write_idx(path: &Path, dims: &[usize], data: &[u8]) {
+        let idx = Idx::new(dims.to_vec(), IdxData::U8(data.to_vec())).unwrap();
+        idx.save(path).unwrap();
+    }
+
+    #[test]
+    fn emnist_works() {
+        let dir = TempDir::new("dlfs-emnist");
+
+        // The pixel at (y=0, x=1) is stored at index 28 (column-major)
+        let mut images = vec![0; IMAGE_SIZE * 2];
+        images[28] = 255;
+        for kind in &["train", "test"] {
+            let images_path = dir.join(format!("emnist-letters-{}-images-idx3-ubyte", kind));
+            let labels_path = dir.join(format!("emnist-letters-{}-labels-idx1-ubyte", kind));
+            write_idx(&images_path, &[2, 28, 28], &images);
+            write_idx(&labels_path, &[2], &[1, 26]);
+        }
+
+        let emnist = Emnist::load(dir.path(), EmnistSplit::Letters).unwrap();
+        assert_eq!(emnist.train_image_count(), 2);
+        assert_eq!(emnist.test_count(), 2);
+        assert_eq!(emnist.train_image(0)[1], 1.0);
+        assert_eq!(emnist.train_image(0)[28], 0.0);
+        assert_eq!(emnist.train_label(0)[0], 1.0);
+        assert_eq!(emnist.test_label(1)[25], 1.0);
+        assert_eq!(emnist.get(1).1.len(), 26);
+        let (x, t) = emnist.choice_train_batch2(3);
+        assert_eq!((x.shape(), t.shape()), ((3, IMAGE_SIZE), (3, 26)));
+
+        write_idx(
+            &dir.join("emnist-letters-test-labels-idx1-ubyte"),
+            &[2],
+            &[0, 1],
+        );
+        assert!(Emnist::load(dir.path(), EmnistSplit::Letters).is_err());
+    }
+}
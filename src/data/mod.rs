@@ -0,0 +1,310 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use std::sync::Arc;
+
+use matrix::Matrix;
+use random;
+
+pub use self::augmented::Augmented;
+pub use self::cifar10::{Cifar10, CIFAR10_CLASSES};
+pub use self::compact::CompactMnist;
+pub use self::csv::{CsvOptions, CsvTable, MissingValue, TabularEncoder};
+pub use self::mnist::{
+    Emnist, EmnistSplit, FashionMnist, Kmnist, Mnist, MnistEntry, MnistOptions,
+    FASHION_MNIST_CLASSES, KMNIST_CLASSES,
+};
+pub use self::prefetch::{PrefetchLoader, PrefetchOptions, Prefetched};
+pub use self::split::{
+    class_indices, cross_validate, k_fold, stratified_split, BalancedSampler, CrossValidation,
+    Subset,
+};
+
+pub mod toy;
+
+mod augmented;
+mod cifar10;
+mod compact;
+mod csv;
+mod mnist;
+mod prefetch;
+mod split;
+
+pub trait Dataset {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Returns the input and the (one-hot) label of the `index`-th sample
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>);
+
+    // Override this if the input of a sample is costly to build
+    fn label(&self, index: usize) -> Vec<f64> {
+        self.get(index).1
+    }
+
+    fn batch(&self, indices: &[usize]) -> (Matrix, Matrix) {
+        let mut x_batch = Vec::with_capacity(indices.len());
+        let mut t_batch = Vec::with_capacity(indices.len());
+        for &i in indices {
+            let (x, t) = self.get(i);
+            x_batch.push(x);
+            t_batch.push(t);
+        }
+        (Matrix::from(x_batch), Matrix::from(t_batch))
+    }
+}
+
+impl<D: Dataset + ?Sized> Dataset for &D {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        (**self).get(index)
+    }
+
+    fn label(&self, index: usize) -> Vec<f64> {
+        (**self).label(index)
+    }
+
+    fn batch(&self, indices: &[usize]) -> (Matrix, Matrix) {
+        (**self).batch(indices)
+    }
+}
+
+impl<D: Dataset + ?Sized> Dataset for Arc<D> {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        (**self).get(index)
+    }
+
+    fn label(&self, index: usize) -> Vec<f64> {
+        (**self).label(index)
+    }
+
+    fn batch(&self, indices: &[usize]) -> (Matrix, Matrix) {
+        (**self).batch(indices)
+    }
+}
+
+// A dataset whose `i`-th sample is the `i`-th rows of `x` and `t`
+#[derive(Debug, Clone)]
+pub struct MatrixDataset {
+    x: Matrix,
+    t: Matrix,
+}
+impl MatrixDataset {
+    pub fn new(x: Matrix, t: Matrix) -> Self {
+        assert_eq!(x.rows(), t.rows());
+        MatrixDataset { x, t }
+    }
+
+    pub fn x(&self) -> &Matrix {
+        &self.x
+    }
+
+    pub fn t(&self) -> &Matrix {
+        &self.t
+    }
+}
+impl Dataset for MatrixDataset {
+    fn len(&self) -> usize {
+        self.x.rows()
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        (
+            Vec::from(self.x.row_slice(index)),
+            Vec::from(self.t.row_slice(index)),
+        )
+    }
+
+    fn label(&self, index: usize) -> Vec<f64> {
+        Vec::from(self.t.row_slice(index))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DataLoaderOptions {
+    pub batch_size: usize,
+    pub shuffle: bool,
+
+    // Drops the last batch of an epoch if it is smaller than `batch_size`
+    pub drop_last: bool,
+}
+impl Default for DataLoaderOptions {
+    fn default() -> Self {
+        DataLoaderOptions {
+            batch_size: 100,
+            shuffle: true,
+            drop_last: false,
+        }
+    }
+}
+
+// Every sample appears exactly once per epoch
+#[derive(Debug)]
+pub struct DataLoader<'a, D: 'a> {
+    dataset: &'a D,
+    options: DataLoaderOptions,
+    rng: StdRng,
+}
+impl<'a, D: Dataset> DataLoader<'a, D> {
+    pub fn new(dataset: &'a D, options: DataLoaderOptions) -> Self {
+        Self::with_rng(dataset, options, random::new_rng())
+    }
+
+    pub fn with_rng(dataset: &'a D, options: DataLoaderOptions, rng: StdRng) -> Self {
+        assert_ne!(options.batch_size, 0);
+        DataLoader {
+            dataset,
+            options,
+            rng,
+        }
+    }
+
+    pub fn batches_per_epoch(&self) -> usize {
+        batches_per_epoch(self.dataset.len(), &self.options)
+    }
+
+    pub fn epoch(&mut self) -> Batches<'a, D> {
+        let indices = epoch_indices(self.dataset.len(), &self.options, &mut self.rng);
+        Batches {
+            dataset: self.dataset,
+            indices,
+            batch_size: self.options.batch_size,
+            position: 0,
+        }
+    }
+}
+
+fn batches_per_epoch(len: usize, options: &DataLoaderOptions) -> usize {
+    if options.drop_last {
+        len / options.batch_size
+    } else {
+        len.div_ceil(options.batch_size)
+    }
+}
+
+// The order of the samples in an epoch
+fn epoch_indices(len: usize, options: &DataLoaderOptions, rng: &mut StdRng) -> Vec<usize> {
+    let mut indices = (0..len).collect::<Vec<_>>();
+    if options.shuffle {
+        indices.shuffle(rng);
+    }
+    if options.drop_last {
+        let n = indices.len() - indices.len() % options.batch_size;
+        indices.truncate(n);
+    }
+    indices
+}
+
+#[derive(Debug)]
+pub struct Batches<'a, D: 'a> {
+    dataset: &'a D,
+    indices: Vec<usize>,
+    batch_size: usize,
+    position: usize,
+}
+impl<'a, D: Dataset> Iterator for Batches<'a, D> {
+    type Item = (Matrix, Matrix);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position == self.indices.len() {
+            return None;
+        }
+        let end = (self.position + self.batch_size).min(self.indices.len());
+        let batch = self.dataset.batch(&self.indices[self.position..end]);
+        self.position = end;
+        Some(batch)
+    }
+}
+
+// Splits flat sample arrays into in-order batches (the last batch may be smaller)
+fn batches<'a>(
+    images: &'a [f64],
+    labels: &'a [f64],
+    image_size: usize,
+    label_size: usize,
+    batch_size: usize,
+) -> impl Iterator<Item = (Matrix, Matrix)> + 'a {
+    assert_ne!(batch_size, 0);
+    images
+        .chunks(image_size * batch_size)
+        .zip(labels.chunks(label_size * batch_size))
+        .map(move |(xs, ys)| {
+            let x_batch = xs.chunks(image_size).map(Vec::from).collect::<Vec<_>>();
+            let y_batch = ys.chunks(label_size).map(Vec::from).collect::<Vec<_>>();
+            (Matrix::from(x_batch), Matrix::from(y_batch))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn batches_works() {
+        let images = vec![0.5; 6 * 5];
+        let labels = vec![0.0; 3 * 5];
+        let shapes = batches(&images, &labels, 6, 3, 2)
+            .map(|(x, t)| (x.shape(), t.shape()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            shapes,
+            [((2, 6), (2, 3)), ((2, 6), (2, 3)), ((1, 6), (1, 3))]
+        );
+    }
+
+    fn sequence_dataset(n: usize) -> MatrixDataset {
+        let x = (0..n).map(|i| vec![i as f64]).collect::<Vec<_>>();
+        let t = (0..n).map(|i| vec![(i % 2) as f64]).collect::<Vec<_>>();
+        MatrixDataset::new(Matrix::from(x), Matrix::from(t))
+    }
+
+    #[test]
+    fn data_loader_works() {
+        let dataset = sequence_dataset(5);
+        let options = DataLoaderOptions {
+            batch_size: 2,
+            ..Default::default()
+        };
+        let mut loader = DataLoader::new(&dataset, options);
+        assert_eq!(loader.batches_per_epoch(), 3);
+        for _ in 0..3 {
+            let batches = loader.epoch().collect::<Vec<_>>();
+            let rows = batches.iter().map(|b| b.0.rows()).collect::<Vec<_>>();
+            assert_eq!(rows, [2, 2, 1]);
+
+            let mut seen = batches
+                .into_iter()
+                .flat_map(|b| b.0.into_vec())
+                .map(|x| x[0] as usize)
+                .collect::<Vec<_>>();
+            seen.sort();
+            assert_eq!(seen, [0, 1, 2, 3, 4]);
+        }
+    }
+
+    #[test]
+    fn data_loader_drop_last_works() {
+        let dataset = sequence_dataset(5);
+        let options = DataLoaderOptions {
+            batch_size: 2,
+            shuffle: false,
+            drop_last: true,
+        };
+        let mut loader = DataLoader::new(&dataset, options);
+        assert_eq!(loader.batches_per_epoch(), 2);
+
+        let batches = loader.epoch().collect::<Vec<_>>();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[1].0.clone().into_vec(), [[2.0], [3.0]]);
+        assert_eq!(batches[1].1.clone().into_vec(), [[0.0], [1.0]]);
+    }
+}
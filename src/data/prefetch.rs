@@ -0,0 +1,304 @@
+use rand::rngs::StdRng;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use super::{batches_per_epoch, epoch_indices, DataLoaderOptions, Dataset};
+use matrix::Matrix;
+use random;
+
+#[derive(Debug, Clone)]
+pub struct PrefetchOptions {
+    pub workers: usize,
+
+    // The maximum number of batches that are prepared ahead of the training thread, in total
+    // (i.e., including the ones being assembled, and regardless of `workers`).
+    // Workers beyond this number stay idle.
+    pub prefetch_batches: usize,
+}
+impl Default for PrefetchOptions {
+    fn default() -> Self {
+        PrefetchOptions {
+            workers: 2,
+            prefetch_batches: 4,
+        }
+    }
+}
+
+// Same as `DataLoader`, but the batches (including augmentation, if the dataset is `Augmented`)
+// are assembled on worker threads while the caller is training on the previous ones.
+// The batches come in the same order as `DataLoader` with the same generator.
+#[derive(Debug)]
+pub struct PrefetchLoader<D> {
+    dataset: Arc<D>,
+    options: DataLoaderOptions,
+    prefetch: PrefetchOptions,
+    rng: StdRng,
+}
+impl<D: Dataset + Send + Sync + 'static> PrefetchLoader<D> {
+    pub fn new(dataset: Arc<D>, options: DataLoaderOptions, prefetch: PrefetchOptions) -> Self {
+        Self::with_rng(dataset, options, prefetch, random::new_rng())
+    }
+
+    pub fn with_rng(
+        dataset: Arc<D>,
+        options: DataLoaderOptions,
+        prefetch: PrefetchOptions,
+        rng: StdRng,
+    ) -> Self {
+        assert_ne!(options.batch_size, 0);
+        assert_ne!(prefetch.workers, 0);
+        assert_ne!(prefetch.prefetch_batches, 0);
+        PrefetchLoader {
+            dataset,
+            options,
+            prefetch,
+            rng,
+        }
+    }
+
+    pub fn batches_per_epoch(&self) -> usize {
+        batches_per_epoch(self.dataset.len(), &self.options)
+    }
+
+    pub fn epoch(&mut self) -> Prefetched {
+        let indices = epoch_indices(self.dataset.len(), &self.options, &mut self.rng);
+        let batches = indices
+            .chunks(self.options.batch_size)
+            .map(Vec::from)
+            .collect::<Vec<_>>();
+        let remaining = batches.len();
+
+        // The `i`-th batch is assembled by the `i % workers`-th worker
+        // once `i < consumed + prefetch_batches`
+        let workers = self.prefetch.workers;
+        let window = Arc::new(Window {
+            limit: self.prefetch.prefetch_batches,
+            progress: Mutex::new(Progress::default()),
+            changed: Condvar::new(),
+        });
+        let mut receivers = Vec::with_capacity(workers);
+        let mut handles = Vec::with_capacity(workers);
+        for w in 0..workers {
+            let (tx, rx) = mpsc::channel();
+            let dataset = Arc::clone(&self.dataset);
+            let window = Arc::clone(&window);
+            let batches = batches
+                .iter()
+                .cloned()
+                .enumerate()
+                .skip(w)
+                .step_by(workers)
+                .collect::<Vec<_>>();
+            let handle = thread::spawn(move || {
+                for (i, indices) in batches {
+                    if !window.wait_for(i) || tx.send(dataset.batch(&indices)).is_err() {
+                        // The epoch has been dropped
+                        return;
+                    }
+                }
+            });
+            receivers.push(rx);
+            handles.push(handle);
+        }
+        Prefetched {
+            receivers,
+            handles,
+            window,
+            position: 0,
+            remaining,
+        }
+    }
+}
+
+// Bounds the number of batches in flight
+#[derive(Debug)]
+struct Window {
+    limit: usize,
+    progress: Mutex<Progress>,
+    changed: Condvar,
+}
+impl Window {
+    // Waits until the `index`-th batch may be assembled, and returns `false` if stopped
+    fn wait_for(&self, index: usize) -> bool {
+        let mut progress = self.progress.lock().expect("Poisoned");
+        while !progress.stopped && index >= progress.consumed + self.limit {
+            progress = self.changed.wait(progress).expect("Poisoned");
+        }
+        !progress.stopped
+    }
+
+    fn update<F: FnOnce(&mut Progress)>(&self, f: F) {
+        f(&mut self.progress.lock().expect("Poisoned"));
+        self.changed.notify_all();
+    }
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    consumed: usize,
+    stopped: bool,
+}
+
+// An epoch of batches; dropping it stops the workers
+#[derive(Debug)]
+pub struct Prefetched {
+    receivers: Vec<Receiver<(Matrix, Matrix)>>,
+    handles: Vec<JoinHandle<()>>,
+    window: Arc<Window>,
+    position: usize,
+    remaining: usize,
+}
+impl Iterator for Prefetched {
+    type Item = (Matrix, Matrix);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let batch = self.receivers[self.position % self.receivers.len()]
+            .recv()
+            .expect("A prefetch worker panicked");
+        self.window.update(|p| p.consumed += 1);
+        self.position += 1;
+        self.remaining -= 1;
+        Some(batch)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+impl Drop for Prefetched {
+    fn drop(&mut self) {
+        self.window.update(|p| p.stopped = true);
+        self.receivers.clear();
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data::{Augmented, DataLoader, MatrixDataset};
+    use image::GaussianNoise;
+    use rand::SeedableRng;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    fn dataset(n: usize) -> MatrixDataset {
+        let x = (0..n).map(|i| vec![i as f64, 1.0]).collect::<Vec<_>>();
+        let t = (0..n).map(|i| vec![(i % 3) as f64]).collect::<Vec<_>>();
+        MatrixDataset::new(Matrix::from(x), Matrix::from(t))
+    }
+
+    #[test]
+    fn prefetch_loader_works() {
+        let options = DataLoaderOptions {
+            batch_size: 3,
+            ..Default::default()
+        };
+        let prefetch = PrefetchOptions {
+            workers: 3,
+            prefetch_batches: 2,
+        };
+        let dataset = Arc::new(dataset(20));
+        let rng = StdRng::seed_from_u64(1);
+        let mut loader =
+            PrefetchLoader::with_rng(Arc::clone(&dataset), options.clone(), prefetch, rng.clone());
+        let mut expected = DataLoader::with_rng(&*dataset, options, rng);
+        assert_eq!(loader.batches_per_epoch(), 7);
+        for _ in 0..2 {
+            let batches = loader.epoch().collect::<Vec<_>>();
+            assert_eq!(batches, expected.epoch().collect::<Vec<_>>());
+        }
+
+        // Stops the workers in the middle of an epoch
+        let mut batches = loader.epoch();
+        assert_eq!(batches.size_hint(), (7, Some(7)));
+        assert!(batches.next().is_some());
+        drop(batches);
+    }
+
+    // Counts the batches that have been started
+    struct Counting {
+        inner: MatrixDataset,
+        started: AtomicUsize,
+    }
+    impl Dataset for Counting {
+        fn len(&self) -> usize {
+            self.inner.len()
+        }
+
+        fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+            self.inner.get(index)
+        }
+
+        fn batch(&self, indices: &[usize]) -> (Matrix, Matrix) {
+            self.started.fetch_add(1, Ordering::SeqCst);
+            self.inner.batch(indices)
+        }
+    }
+
+    #[test]
+    fn prefetch_batches_bounds_all_workers() {
+        let dataset = Arc::new(Counting {
+            inner: dataset(40),
+            started: AtomicUsize::new(0),
+        });
+        let options = DataLoaderOptions {
+            batch_size: 2,
+            ..Default::default()
+        };
+        let prefetch = PrefetchOptions {
+            workers: 4,
+            prefetch_batches: 2,
+        };
+        let mut loader = PrefetchLoader::new(Arc::clone(&dataset), options, prefetch);
+        let mut batches = loader.epoch();
+        thread::sleep(Duration::from_millis(20));
+        assert!(dataset.started.load(Ordering::SeqCst) <= 2);
+        for consumed in 1..=20 {
+            assert!(batches.next().is_some());
+            thread::sleep(Duration::from_millis(1));
+            assert!(dataset.started.load(Ordering::SeqCst) <= consumed + 2);
+        }
+        assert!(batches.next().is_none());
+    }
+
+    #[test]
+    fn augmentation_is_reproducible_with_workers() {
+        let run = || {
+            let dataset = Augmented::with_rng(
+                dataset(30),
+                (1, 1, 2),
+                GaussianNoise { std: 1.0 },
+                StdRng::seed_from_u64(7),
+            );
+            let options = DataLoaderOptions {
+                batch_size: 4,
+                ..Default::default()
+            };
+            let prefetch = PrefetchOptions {
+                workers: 3,
+                prefetch_batches: 6,
+            };
+            let mut loader = PrefetchLoader::with_rng(
+                Arc::new(dataset),
+                options,
+                prefetch,
+                StdRng::seed_from_u64(8),
+            );
+            (0..3)
+                .flat_map(|_| loader.epoch().collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        };
+        let batches = run();
+        assert_eq!(batches.len(), 3 * 8);
+        for _ in 0..3 {
+            assert_eq!(run(), batches);
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use super::Dataset;
+use functions::argmax;
+use matrix::Matrix;
+use random;
+
+// The samples of `dataset` picked by `indices` (e.g., `Subset::new(&mnist, indices)`)
+#[derive(Debug, Clone)]
+pub struct Subset<D> {
+    dataset: D,
+    indices: Vec<usize>,
+}
+impl<D: Dataset> Subset<D> {
+    pub fn new(dataset: D, indices: Vec<usize>) -> Self {
+        assert!(indices.iter().all(|&i| i < dataset.len()));
+        Subset { dataset, indices }
+    }
+
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    pub fn inner(&self) -> &D {
+        &self.dataset
+    }
+}
+impl<D: Dataset> Dataset for Subset<D> {
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn get(&self, index: usize) -> (Vec<f64>, Vec<f64>) {
+        self.dataset.get(self.indices[index])
+    }
+
+    fn label(&self, index: usize) -> Vec<f64> {
+        self.dataset.label(self.indices[index])
+    }
+
+    fn batch(&self, indices: &[usize]) -> (Matrix, Matrix) {
+        let indices = indices.iter().map(|&i| self.indices[i]).collect::<Vec<_>>();
+        self.dataset.batch(&indices)
+    }
+}
+
+// `classes[c]` holds the indices of the samples whose (one-hot) label is `c`
+pub fn class_indices<D: Dataset>(dataset: &D) -> Vec<Vec<usize>> {
+    let mut classes = Vec::<Vec<usize>>::new();
+    for i in 0..dataset.len() {
+        let label = dataset.label(i);
+        if classes.len() < label.len() {
+            classes.resize(label.len(), Vec::new());
+        }
+        classes[argmax(&label)].push(i);
+    }
+    classes
+}
+
+// Splits `dataset` into (train, validation) so that both keep the class proportions.
+// The samples of each subset stay in the original order.
+pub fn stratified_split<D: Dataset + Clone>(
+    dataset: D,
+    validation_ratio: f64,
+) -> (Subset<D>, Subset<D>) {
+    assert!((0.0..=1.0).contains(&validation_ratio));
+    let mut train = Vec::new();
+    let mut validation = Vec::new();
+    for mut indices in class_indices(&dataset) {
+        random::with_rng(|rng| indices.shuffle(rng));
+        let n = (indices.len() as f64 * validation_ratio).round() as usize;
+        validation.extend_from_slice(&indices[..n]);
+        train.extend_from_slice(&indices[n..]);
+    }
+    train.sort();
+    validation.sort();
+    (
+        Subset::new(dataset.clone(), train),
+        Subset::new(dataset, validation),
+    )
+}
+
+// Stratified k-fold cross validation: returns `k` (train, validation) pairs,
+// and every sample appears in exactly one of the validation subsets
+pub fn k_fold<D: Dataset + Clone>(dataset: D, k: usize) -> Vec<(Subset<D>, Subset<D>)> {
+    assert!(k >= 2);
+    let mut folds = vec![Vec::new(); k];
+    let mut next = 0;
+    for mut indices in class_indices(&dataset) {
+        random::with_rng(|rng| indices.shuffle(rng));
+        for i in indices {
+            folds[next % k].push(i);
+            next += 1;
+        }
+    }
+    for fold in &mut folds {
+        fold.sort();
+    }
+    (0..k)
+        .map(|i| {
+            let mut train = folds
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .flat_map(|(_, f)| f.iter().cloned())
+                .collect::<Vec<_>>();
+            train.sort();
+            (
+                Subset::new(dataset.clone(), train),
+                Subset::new(dataset.clone(), folds[i].clone()),
+            )
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct CrossValidation {
+    // One score per fold
+    pub scores: Vec<f64>,
+    pub mean: f64,
+
+    // The population standard deviation of `scores`
+    pub std: f64,
+}
+
+// Runs `evaluate(train, validation)` on each of the `k` folds and summarizes the returned scores
+// (e.g., training a fresh `ch05::TwoLayerNet` with a candidate hyperparameter and returning
+// its validation accuracy)
+pub fn cross_validate<D, F>(dataset: D, k: usize, mut evaluate: F) -> CrossValidation
+where
+    D: Dataset + Clone,
+    F: FnMut(&Subset<D>, &Subset<D>) -> f64,
+{
+    let scores = k_fold(dataset, k)
+        .iter()
+        .map(|(train, validation)| evaluate(train, validation))
+        .collect::<Vec<_>>();
+    let mean = scores.iter().sum::<f64>() / k as f64;
+    let variance = scores.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / k as f64;
+    CrossValidation {
+        scores,
+        mean,
+        std: variance.sqrt(),
+    }
+}
+
+// Draws batches (with replacement) in which every class appears with the same probability
+#[derive(Debug)]
+pub struct BalancedSampler<D> {
+    dataset: D,
+    classes: Vec<Vec<usize>>,
+    rng: StdRng,
+}
+impl<D: Dataset> BalancedSampler<D> {
+    pub fn new(dataset: D) -> Self {
+        Self::with_rng(dataset, random::new_rng())
+    }
+
+    pub fn with_rng(dataset: D, rng: StdRng) -> Self {
+        let classes = class_indices(&dataset)
+            .into_iter()
+            .filter(|c| !c.is_empty())
+            .collect::<Vec<_>>();
+        assert!(!classes.is_empty(), "Empty dataset");
+        BalancedSampler {
+            dataset,
+            classes,
+            rng,
+        }
+    }
+
+    pub fn sample_indices(&mut self, n: usize) -> Vec<usize> {
+        let rng = &mut self.rng;
+        let classes = &self.classes;
+        (0..n)
+            .map(|_| {
+                let class = &classes[rng.gen_range(0, classes.len())];
+                class[rng.gen_range(0, class.len())]
+            })
+            .collect()
+    }
+
+    pub fn batch(&mut self, batch_size: usize) -> (Matrix, Matrix) {
+        let indices = self.sample_indices(batch_size);
+        self.dataset.batch(&indices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ch05::TwoLayerNet;
+    use data::MatrixDataset;
+    use rand::SeedableRng;
+
+    // 12, 6 and 3 samples of the classes 0, 1 and 2
+    fn imbalanced() -> MatrixDataset {
+        let classes = (0..21)
+            .map(|i| match i {
+                0..=11 => 0,
+                12..=17 => 1,
+                _ => 2,
+            })
+            .collect::<Vec<_>>();
+        let x = (0..21).map(|i| vec![i as f64]).collect::<Vec<_>>();
+        let t = classes
+            .iter()
+            .map(|&c| (0..3).map(|j| if j == c { 1.0 } else { 0.0 }).collect())
+            .collect::<Vec<_>>();
+        MatrixDataset::new(Matrix::from(x), Matrix::from(t))
+    }
+
+    fn class_counts<D: Dataset>(dataset: &D) -> Vec<usize> {
+        class_indices(dataset).iter().map(Vec::len).collect()
+    }
+
+    #[test]
+    fn stratified_split_works() {
+        let dataset = imbalanced();
+        let (train, validation) = stratified_split(&dataset, 1.0 / 3.0);
+        assert_eq!(class_counts(&train), [8, 4, 2]);
+        assert_eq!(class_counts(&validation), [4, 2, 1]);
+
+        let mut all = train.indices().to_vec();
+        all.extend_from_slice(validation.indices());
+        all.sort();
+        assert_eq!(all, (0..21).collect::<Vec<_>>());
+        assert_eq!(validation.get(0).0[0], validation.indices()[0] as f64);
+    }
+
+    #[test]
+    fn k_fold_works() {
+        let dataset = imbalanced();
+        let folds = k_fold(&dataset, 3);
+        assert_eq!(folds.len(), 3);
+
+        let mut seen = Vec::new();
+        for (train, validation) in &folds {
+            assert_eq!(train.len() + validation.len(), 21);
+            assert_eq!(class_counts(validation), [4, 2, 1]);
+            assert!(validation
+                .indices()
+                .iter()
+                .all(|i| !train.indices().contains(i)));
+            seen.extend_from_slice(validation.indices());
+        }
+        seen.sort();
+        assert_eq!(seen, (0..21).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn cross_validate_works() {
+        let result = cross_validate(imbalanced(), 3, |_, validation| validation.len() as f64);
+        assert_eq!(result.scores, [7.0, 7.0, 7.0]);
+        assert_eq!((result.mean, result.std), (7.0, 0.0));
+
+        // Compares the hidden sizes of `TwoLayerNet` on points classified by `x0 > x1`
+        random::seed(0);
+        let x = (0..60)
+            .map(|i| vec![(i % 10) as f64 / 10.0, (i / 10) as f64 / 6.0])
+            .collect::<Vec<_>>();
+        let t = x
+            .iter()
+            .map(|x| {
+                if x[0] > x[1] {
+                    vec![1.0, 0.0]
+                } else {
+                    vec![0.0, 1.0]
+                }
+            })
+            .collect::<Vec<_>>();
+        let dataset = MatrixDataset::new(Matrix::from(x), Matrix::from(t));
+        for &hidden_size in &[2, 10] {
+            let result = cross_validate(&dataset, 3, |train, validation| {
+                let mut net = TwoLayerNet::new(2, hidden_size, 2);
+                net.train(train, 300, 10, 0.5);
+                let (x, t) = validation.batch(&(0..validation.len()).collect::<Vec<_>>());
+                net.accuracy(x, t)
+            });
+            assert_eq!(result.scores.len(), 3);
+            assert!(result.mean > 0.8, "{:?}", result);
+            assert!(result.std < 0.2, "{:?}", result);
+        }
+    }
+
+    #[test]
+    fn balanced_sampler_works() {
+        let dataset = imbalanced();
+        let mut sampler = BalancedSampler::with_rng(&dataset, StdRng::seed_from_u64(0));
+        let (x, t) = sampler.batch(3000);
+        assert_eq!(x.shape(), (3000, 1));
+
+        let mut counts = [0; 3];
+        for i in 0..t.rows() {
+            counts[argmax(t.row_slice(i))] += 1;
+        }
+        for &c in &counts {
+            assert!((c as f64 / 3000.0 - 1.0 / 3.0).abs() < 0.05, "{:?}", counts);
+        }
+    }
+}
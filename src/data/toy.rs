@@ -0,0 +1,239 @@
+// Small synthetic datasets for tests and demos.
+// Each generator returns `(x, t)` where `t` holds one-hot labels (except `linear_regression`),
+// and the samples are ordered by class.
+use rand::distributions::StandardNormal;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f64::consts::PI;
+
+use matrix::Matrix;
+
+// Interleaved spiral arms in 2D (one arm per class)
+pub fn spirals(
+    samples_per_class: usize,
+    classes: usize,
+    noise: f64,
+    seed: u64,
+) -> (Matrix, Matrix) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut x = Vec::new();
+    let mut t = Vec::new();
+    for c in 0..classes {
+        for i in 0..samples_per_class {
+            let r = i as f64 / samples_per_class.max(2).saturating_sub(1) as f64;
+            let theta = c as f64 * 2.0 * PI / classes as f64 + r * 4.0 + gaussian(&mut rng) * noise;
+            x.push(vec![r * theta.sin(), r * theta.cos()]);
+            t.push(one_hot(c, classes));
+        }
+    }
+    (Matrix::from(x), Matrix::from(t))
+}
+
+// Two interleaving half circles: `samples / 2` points on the first one (class 0)
+// and the rest on the second one (class 1), which gets the extra point if `samples` is odd
+pub fn moons(samples: usize, noise: f64, seed: u64) -> (Matrix, Matrix) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let outer = samples / 2;
+    let mut x = Vec::with_capacity(samples);
+    let mut t = Vec::with_capacity(samples);
+    for i in 0..samples {
+        let (class, j, n) = if i < outer {
+            (0, i, outer)
+        } else {
+            (1, i - outer, samples - outer)
+        };
+        let angle = PI * j as f64 / n.max(2).saturating_sub(1) as f64;
+        let (px, py) = if class == 0 {
+            (angle.cos(), angle.sin())
+        } else {
+            (1.0 - angle.cos(), 0.5 - angle.sin())
+        };
+        x.push(vec![
+            px + gaussian(&mut rng) * noise,
+            py + gaussian(&mut rng) * noise,
+        ]);
+        t.push(one_hot(class, 2));
+    }
+    (Matrix::from(x), Matrix::from(t))
+}
+
+// A large circle (class 0) containing a smaller one (class 1) whose radius is `factor`
+pub fn circles(samples: usize, factor: f64, noise: f64, seed: u64) -> (Matrix, Matrix) {
+    assert!(0.0 < factor && factor < 1.0);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let outer = samples / 2;
+    let mut x = Vec::with_capacity(samples);
+    let mut t = Vec::with_capacity(samples);
+    for i in 0..samples {
+        let (class, j, n, r) = if i < outer {
+            (0, i, outer, 1.0)
+        } else {
+            (1, i - outer, samples - outer, factor)
+        };
+        let angle = 2.0 * PI * j as f64 / n as f64;
+        x.push(vec![
+            r * angle.cos() + gaussian(&mut rng) * noise,
+            r * angle.sin() + gaussian(&mut rng) * noise,
+        ]);
+        t.push(one_hot(class, 2));
+    }
+    (Matrix::from(x), Matrix::from(t))
+}
+
+// Points uniformly drawn from `[-1, 1]^2`; the class is 1 if the signs of the coordinates differ.
+// `noise` is added after labeling, so some points may cross the axes.
+pub fn xor(samples: usize, noise: f64, seed: u64) -> (Matrix, Matrix) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut points = (0..samples)
+        .map(|_| (rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0)))
+        .map(|(a, b): (f64, f64)| ((a * b < 0.0) as usize, a, b))
+        .collect::<Vec<_>>();
+    points.sort_by_key(|p| p.0);
+
+    let mut x = Vec::with_capacity(samples);
+    let mut t = Vec::with_capacity(samples);
+    for (class, a, b) in points {
+        x.push(vec![
+            a + gaussian(&mut rng) * noise,
+            b + gaussian(&mut rng) * noise,
+        ]);
+        t.push(one_hot(class, 2));
+    }
+    (Matrix::from(x), Matrix::from(t))
+}
+
+// Isotropic Gaussian clusters around `centers` (one class per center)
+pub fn blobs(
+    samples_per_class: usize,
+    centers: &[Vec<f64>],
+    std: f64,
+    seed: u64,
+) -> (Matrix, Matrix) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut x = Vec::new();
+    let mut t = Vec::new();
+    for (c, center) in centers.iter().enumerate() {
+        for _ in 0..samples_per_class {
+            x.push(
+                center
+                    .iter()
+                    .map(|v| v + gaussian(&mut rng) * std)
+                    .collect(),
+            );
+            t.push(one_hot(c, centers.len()));
+        }
+    }
+    (Matrix::from(x), Matrix::from(t))
+}
+
+// `x` is drawn from the standard normal distribution and `t = x * weights + bias + noise`.
+// `t` has a single column.
+pub fn linear_regression(
+    samples: usize,
+    weights: &[f64],
+    bias: f64,
+    noise: f64,
+    seed: u64,
+) -> (Matrix, Matrix) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut x = Vec::with_capacity(samples);
+    let mut t = Vec::with_capacity(samples);
+    for _ in 0..samples {
+        let xs = weights
+            .iter()
+            .map(|_| gaussian(&mut rng))
+            .collect::<Vec<_>>();
+        let y = xs.iter().zip(weights).map(|(a, w)| a * w).sum::<f64>()
+            + bias
+            + gaussian(&mut rng) * noise;
+        x.push(xs);
+        t.push(vec![y]);
+    }
+    (Matrix::from(x), Matrix::from(t))
+}
+
+fn gaussian(rng: &mut StdRng) -> f64 {
+    rng.sample(StandardNormal)
+}
+
+fn one_hot(class: usize, classes: usize) -> Vec<f64> {
+    let mut t = vec![0.0; classes];
+    t[class] = 1.0;
+    t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ch05::TwoLayerNet;
+    use data::MatrixDataset;
+    use functions::argmax;
+    use random;
+
+    fn class_counts(t: &Matrix) -> Vec<usize> {
+        let mut counts = vec![0; t.columns()];
+        for i in 0..t.rows() {
+            counts[argmax(t.row_slice(i))] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn shapes_and_labels_work() {
+        let (x, t) = spirals(10, 3, 0.1, 0);
+        assert_eq!((x.shape(), t.shape()), ((30, 2), (30, 3)));
+        assert_eq!(class_counts(&t), [10, 10, 10]);
+
+        let (x, t) = moons(11, 0.1, 0);
+        assert_eq!((x.shape(), class_counts(&t)), ((11, 2), vec![5, 6]));
+
+        let (x, t) = circles(20, 0.5, 0.0, 0);
+        assert_eq!(class_counts(&t), [10, 10]);
+        for i in 0..20 {
+            let r = x.row(i).map(|v| v * v).sum::<f64>().sqrt();
+            let expected = if i < 10 { 1.0 } else { 0.5 };
+            assert!((r - expected).abs() < 1e-12);
+        }
+
+        let (x, t) = xor(50, 0.0, 0);
+        for i in 0..50 {
+            let p = x.row_slice(i);
+            assert_eq!(argmax(t.row_slice(i)) == 1, p[0] * p[1] < 0.0);
+        }
+
+        let centers = vec![vec![0.0, 0.0, 0.0], vec![5.0, 5.0, 5.0]];
+        let (x, t) = blobs(4, &centers, 0.1, 0);
+        assert_eq!((x.shape(), t.shape()), ((8, 3), (8, 2)));
+        assert!(x.row(7).all(|&v| (v - 5.0).abs() < 1.0));
+    }
+
+    #[test]
+    fn linear_regression_works() {
+        let (x, t) = linear_regression(5, &[2.0, -1.0], 0.5, 0.0, 0);
+        assert_eq!((x.shape(), t.shape()), ((5, 2), (5, 1)));
+        for i in 0..5 {
+            let p = x.row_slice(i);
+            assert!((t.row_slice(i)[0] - (2.0 * p[0] - p[1] + 0.5)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn two_layer_net_learns_toy_data() {
+        random::seed(0);
+        let data = vec![("xor", xor(200, 0.0, 0)), ("moons", moons(200, 0.1, 0))];
+        for (name, (x, t)) in data {
+            let dataset = MatrixDataset::new(x.clone(), t.clone());
+            let mut net = TwoLayerNet::new(2, 16, 2);
+            net.train(&dataset, 300, 20, 0.5);
+            let accuracy = net.accuracy(x, t);
+            assert!(accuracy > 0.9, "{}: {}", name, accuracy);
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_data() {
+        assert_eq!(moons(20, 0.1, 3), moons(20, 0.1, 3));
+        assert_ne!(moons(20, 0.1, 3), moons(20, 0.1, 4));
+        assert_eq!(spirals(5, 2, 0.2, 3), spirals(5, 2, 0.2, 3));
+    }
+}
@@ -1,5 +1,3 @@
-use std::f64::EPSILON;
-
 pub fn mean_squared_error(predicted: &[f64], observed: &[f64]) -> f64 {
     0.5 * predicted
         .iter()
@@ -12,7 +10,7 @@ pub fn cross_entropy_error(predicted: &[f64], observed: &[f64]) -> f64 {
     -predicted
         .iter()
         .zip(observed.iter())
-        .map(|t| *t.1 * (if *t.0 == 0.0 { EPSILON } else { *t.0 }).ln())
+        .map(|t| *t.1 * (if *t.0 == 0.0 { f64::EPSILON } else { *t.0 }).ln())
         .sum::<f64>()
 }
 
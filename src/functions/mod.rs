@@ -5,9 +5,9 @@ pub fn argmax(xs: &[f64]) -> usize {
     assert_ne!(xs.len(), 0);
     let mut max_value = xs[0];
     let mut max_index = 0;
-    for i in 1..xs.len() {
-        if max_value < xs[i] {
-            max_value = xs[i];
+    for (i, &x) in xs.iter().enumerate().skip(1) {
+        if max_value < x {
+            max_value = x;
             max_index = i;
         }
     }
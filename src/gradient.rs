@@ -1,3 +1,5 @@
+use matrix::Matrix;
+
 pub fn numerical_gradient<F>(f: F, xs: &[f64]) -> Vec<f64>
 where
     F: Fn(&[f64]) -> f64,
@@ -24,12 +26,70 @@ where
 {
     for _ in 0..step_num {
         let grads = numerical_gradient(&f, xs);
-        for (x, grad) in xs.iter_mut().zip(grads.into_iter()) {
+        for (x, grad) in xs.iter_mut().zip(grads) {
             *x -= learning_rate * grad;
         }
     }
 }
 
+pub trait Differentiable {
+    fn parameter_names(&self) -> Vec<&'static str>;
+
+    fn parameter_mut(&mut self, index: usize) -> &mut Matrix;
+
+    fn loss(&mut self, x: &Matrix, t: &Matrix) -> f64;
+
+    // Returns the analytic gradients in the same order as `parameter_names()`
+    fn gradients(&mut self, x: &Matrix, t: &Matrix) -> Vec<Matrix>;
+}
+
+#[derive(Debug, Clone)]
+pub struct GradientError {
+    pub name: &'static str,
+    pub max_absolute_error: f64,
+    pub max_relative_error: f64,
+}
+
+pub fn gradient_check<M>(model: &mut M, x: &Matrix, t: &Matrix) -> Vec<GradientError>
+where
+    M: Differentiable,
+{
+    let analytic_grads = model.gradients(x, t);
+    let names = model.parameter_names();
+    assert_eq!(names.len(), analytic_grads.len());
+
+    let mut errors = Vec::with_capacity(names.len());
+    for (i, (name, analytic)) in names.into_iter().zip(analytic_grads).enumerate() {
+        let original = model.parameter_mut(i).clone();
+        let numerical = original.clone().numerical_gradient(|m| {
+            *model.parameter_mut(i) = m.clone();
+            model.loss(x, t)
+        });
+        *model.parameter_mut(i) = original;
+
+        assert_eq!(analytic.shape(), numerical.shape(), "parameter={:?}", name);
+        let mut max_absolute_error: f64 = 0.0;
+        let mut max_relative_error: f64 = 0.0;
+        let pairs = analytic
+            .into_vec()
+            .into_iter()
+            .flatten()
+            .zip(numerical.into_vec().into_iter().flatten());
+        for (a, n) in pairs {
+            let absolute_error = (a - n).abs();
+            let relative_error = absolute_error / (a.abs() + n.abs()).max(1e-8);
+            max_absolute_error = max_absolute_error.max(absolute_error);
+            max_relative_error = max_relative_error.max(relative_error);
+        }
+        errors.push(GradientError {
+            name,
+            max_absolute_error,
+            max_relative_error,
+        });
+    }
+    errors
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
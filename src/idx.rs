@@ -0,0 +1,568 @@
+// IDX file format: http://yann.lecun.com/exdb/mnist/
+//
+// magic number (0x00, 0x00, element type, number of dimensions),
+// the size of each dimension (u32), and then the elements; all big-endian.
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::result;
+
+use matrix::Matrix;
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    InvalidMagicNumber([u8; 4]),
+    UnknownElementType(u8),
+    SizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    TooLargeDimension(usize),
+    OutOfRange {
+        start: usize,
+        count: usize,
+        len: usize,
+    },
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::InvalidMagicNumber(m) => write!(f, "Invalid IDX magic number: {:?}", m),
+            Error::UnknownElementType(t) => write!(f, "Unknown IDX element type: 0x{:02X}", t),
+            Error::SizeMismatch { expected, actual } => write!(
+                f,
+                "Expected {} elements, but got {} (or the data is truncated)",
+                expected, actual
+            ),
+            Error::TooLargeDimension(d) => write!(f, "Too large IDX dimension: {}", d),
+            Error::OutOfRange { start, count, len } => write!(
+                f,
+                "Items [{}, {} + {}) are out of range (len={})",
+                start, start, count, len
+            ),
+        }
+    }
+}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+impl From<io::Error> for Error {
+    fn from(f: io::Error) -> Self {
+        Error::Io(f)
+    }
+}
+impl From<Error> for io::Error {
+    fn from(f: Error) -> Self {
+        match f {
+            Error::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElementType {
+    U8,
+    I8,
+    I16,
+    I32,
+    F32,
+    F64,
+}
+impl ElementType {
+    pub fn from_code(code: u8) -> Result<Self> {
+        match code {
+            0x08 => Ok(ElementType::U8),
+            0x09 => Ok(ElementType::I8),
+            0x0B => Ok(ElementType::I16),
+            0x0C => Ok(ElementType::I32),
+            0x0D => Ok(ElementType::F32),
+            0x0E => Ok(ElementType::F64),
+            _ => Err(Error::UnknownElementType(code)),
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            ElementType::U8 => 0x08,
+            ElementType::I8 => 0x09,
+            ElementType::I16 => 0x0B,
+            ElementType::I32 => 0x0C,
+            ElementType::F32 => 0x0D,
+            ElementType::F64 => 0x0E,
+        }
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            ElementType::U8 | ElementType::I8 => 1,
+            ElementType::I16 => 2,
+            ElementType::I32 | ElementType::F32 => 4,
+            ElementType::F64 => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdxData {
+    U8(Vec<u8>),
+    I8(Vec<i8>),
+    I16(Vec<i16>),
+    I32(Vec<i32>),
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+}
+impl IdxData {
+    pub fn element_type(&self) -> ElementType {
+        match self {
+            IdxData::U8(_) => ElementType::U8,
+            IdxData::I8(_) => ElementType::I8,
+            IdxData::I16(_) => ElementType::I16,
+            IdxData::I32(_) => ElementType::I32,
+            IdxData::F32(_) => ElementType::F32,
+            IdxData::F64(_) => ElementType::F64,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            IdxData::U8(v) => v.len(),
+            IdxData::I8(v) => v.len(),
+            IdxData::I16(v) => v.len(),
+            IdxData::I32(v) => v.len(),
+            IdxData::F32(v) => v.len(),
+            IdxData::F64(v) => v.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn to_f64_vec(&self) -> Vec<f64> {
+        match self {
+            IdxData::U8(v) => v.iter().map(|&x| f64::from(x)).collect(),
+            IdxData::I8(v) => v.iter().map(|&x| f64::from(x)).collect(),
+            IdxData::I16(v) => v.iter().map(|&x| f64::from(x)).collect(),
+            IdxData::I32(v) => v.iter().map(|&x| f64::from(x)).collect(),
+            IdxData::F32(v) => v.iter().map(|&x| f64::from(x)).collect(),
+            IdxData::F64(v) => v.clone(),
+        }
+    }
+
+    fn decode(element_type: ElementType, bytes: &[u8]) -> Self {
+        let size = element_type.size();
+        let chunks = bytes.chunks(size);
+        match element_type {
+            ElementType::U8 => IdxData::U8(bytes.to_vec()),
+            ElementType::I8 => IdxData::I8(bytes.iter().map(|&b| b as i8).collect()),
+            ElementType::I16 => {
+                IdxData::I16(chunks.map(|b| i16::from_be_bytes([b[0], b[1]])).collect())
+            }
+            ElementType::I32 => IdxData::I32(
+                chunks
+                    .map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect(),
+            ),
+            ElementType::F32 => IdxData::F32(
+                chunks
+                    .map(|b| f32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect(),
+            ),
+            ElementType::F64 => IdxData::F64(
+                chunks
+                    .map(|b| {
+                        let mut buf = [0; 8];
+                        buf.copy_from_slice(b);
+                        f64::from_be_bytes(buf)
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    fn encode<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        match self {
+            IdxData::U8(v) => writer.write_all(v),
+            IdxData::I8(v) => writer.write_all(&v.iter().map(|&x| x as u8).collect::<Vec<_>>()),
+            IdxData::I16(v) => v
+                .iter()
+                .try_for_each(|x| writer.write_all(&x.to_be_bytes())),
+            IdxData::I32(v) => v
+                .iter()
+                .try_for_each(|x| writer.write_all(&x.to_be_bytes())),
+            IdxData::F32(v) => v
+                .iter()
+                .try_for_each(|x| writer.write_all(&x.to_be_bytes())),
+            IdxData::F64(v) => v
+                .iter()
+                .try_for_each(|x| writer.write_all(&x.to_be_bytes())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Idx {
+    dims: Vec<usize>,
+    data: IdxData,
+}
+impl Idx {
+    pub fn new(dims: Vec<usize>, data: IdxData) -> Result<Self> {
+        let expected = element_count(&dims)?;
+        if data.len() != expected {
+            return Err(Error::SizeMismatch {
+                expected,
+                actual: data.len(),
+            });
+        }
+        if let Some(&d) = dims.iter().find(|&&d| d > u32::MAX as usize) {
+            return Err(Error::TooLargeDimension(d));
+        }
+        if dims.len() > u8::MAX as usize {
+            return Err(Error::TooLargeDimension(dims.len()));
+        }
+        Ok(Idx { dims, data })
+    }
+
+    // (rows, columns) => [rows, columns] of `F64` elements
+    pub fn from_matrix(m: &Matrix) -> Self {
+        let (rows, columns) = m.shape();
+        let data = m.clone().into_vec().into_iter().flatten().collect();
+        Idx {
+            dims: vec![rows, columns],
+            data: IdxData::F64(data),
+        }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path)?;
+        Self::read_from(BufReader::new(file))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self> {
+        let (element_type, dims, expected) = read_header(&mut reader)?;
+        let expected_bytes = expected
+            .checked_mul(element_type.size())
+            .ok_or_else(|| too_large(&dims))?;
+
+        // Reads at most one extra byte, so that a lying header cannot make us buffer the whole input
+        let mut bytes = Vec::new();
+        reader
+            .take((expected_bytes as u64).saturating_add(1))
+            .read_to_end(&mut bytes)?;
+        if bytes.len() != expected_bytes {
+            return Err(Error::SizeMismatch {
+                expected,
+                actual: bytes.len() / element_type.size(),
+            });
+        }
+        let data = IdxData::decode(element_type, &bytes);
+        Ok(Idx { dims, data })
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<()> {
+        writer.write_all(&[0, 0, self.data.element_type().code(), self.dims.len() as u8])?;
+        for &d in &self.dims {
+            writer.write_all(&(d as u32).to_be_bytes())?;
+        }
+        self.data.encode(&mut writer)?;
+        Ok(())
+    }
+
+    pub fn dims(&self) -> &[usize] {
+        &self.dims
+    }
+
+    pub fn data(&self) -> &IdxData {
+        &self.data
+    }
+
+    pub fn into_data(self) -> IdxData {
+        self.data
+    }
+
+    // The first dimension becomes the rows and the rest is flattened into the columns
+    // (e.g., [60000, 28, 28] => (60000, 784), [60000] => (60000, 1))
+    pub fn to_matrix(&self) -> Matrix {
+        let rows = self.dims.first().cloned().unwrap_or(0);
+        let columns = self.dims.iter().skip(1).product::<usize>();
+        let values = self.data.to_f64_vec();
+        let m = values
+            .chunks(columns.max(1))
+            .take(rows)
+            .map(Vec::from)
+            .collect::<Vec<_>>();
+        Matrix::from(m)
+    }
+}
+
+// Reads the items (i.e., the sub-arrays along the first dimension) of an IDX file on demand
+// instead of loading the whole file.
+// Reads are positional, so threads sharing a reader do not wait for each other.
+#[derive(Debug)]
+pub struct IdxReader {
+    file: File,
+    element_type: ElementType,
+    dims: Vec<usize>,
+    header_size: u64,
+}
+impl IdxReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let (element_type, dims, expected) = read_header(BufReader::new(&mut file))?;
+        if dims.is_empty() {
+            return Err(Error::SizeMismatch {
+                expected: 1,
+                actual: 0,
+            });
+        }
+        let header_size = 4 + 4 * dims.len() as u64;
+        let expected_bytes = expected
+            .checked_mul(element_type.size())
+            .ok_or_else(|| too_large(&dims))?;
+
+        // Extra bytes and a truncated last element are rejected too
+        let actual_bytes = file.metadata()?.len().saturating_sub(header_size);
+        if actual_bytes != expected_bytes as u64 {
+            return Err(Error::SizeMismatch {
+                expected,
+                actual: (actual_bytes / element_type.size() as u64) as usize,
+            });
+        }
+        Ok(IdxReader {
+            file,
+            element_type,
+            dims,
+            header_size,
+        })
+    }
+
+    pub fn dims(&self) -> &[usize] {
+        &self.dims
+    }
+
+    pub fn element_type(&self) -> ElementType {
+        self.element_type
+    }
+
+    pub fn len(&self) -> usize {
+        self.dims[0]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The number of elements of an item
+    pub fn item_size(&self) -> usize {
+        self.dims[1..].iter().product()
+    }
+
+    pub fn read_item(&self, index: usize) -> Result<IdxData> {
+        self.read_items(index, 1)
+    }
+
+    // Reads the items in `[start, start + count)` (concatenated)
+    pub fn read_items(&self, start: usize, count: usize) -> Result<IdxData> {
+        if start.checked_add(count).is_none_or(|end| end > self.len()) {
+            return Err(Error::OutOfRange {
+                start,
+                count,
+                len: self.len(),
+            });
+        }
+        let item_bytes = self.item_size() * self.element_type.size();
+        // Never overflow because `open` checked the size of the whole file
+        let mut bytes = vec![0; item_bytes * count];
+        let offset = self.header_size + (start * item_bytes) as u64;
+        read_exact_at(&self.file, &mut bytes, offset)?;
+        Ok(IdxData::decode(self.element_type, &bytes))
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.read_exact_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+// Returns the element type, the dimensions and the number of elements
+fn read_header<R: Read>(mut reader: R) -> Result<(ElementType, Vec<usize>, usize)> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic[0] != 0 || magic[1] != 0 {
+        return Err(Error::InvalidMagicNumber(magic));
+    }
+    let element_type = ElementType::from_code(magic[2])?;
+
+    let mut dims = Vec::with_capacity(magic[3] as usize);
+    for _ in 0..magic[3] {
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf)?;
+        dims.push(u32::from_be_bytes(buf) as usize);
+    }
+
+    let count = element_count(&dims)?;
+    Ok((element_type, dims, count))
+}
+
+fn element_count(dims: &[usize]) -> Result<usize> {
+    dims.iter()
+        .try_fold(1usize, |acc, &d| acc.checked_mul(d))
+        .ok_or_else(|| too_large(dims))
+}
+
+fn too_large(dims: &[usize]) -> Error {
+    Error::TooLargeDimension(dims.iter().cloned().max().unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(idx: &Idx) -> Idx {
+        let mut buf = Vec::new();
+        idx.write_to(&mut buf).unwrap();
+        Idx::read_from(&buf[..]).unwrap()
+    }
+
+    #[test]
+    fn read_and_write_works() {
+        let data = vec![
+            IdxData::U8(vec![0, 1, 2, 3, 254, 255]),
+            IdxData::I8(vec![0, 1, -2, 3, -128, 127]),
+            IdxData::I16(vec![0, 1, -2, 3, i16::MIN, i16::MAX]),
+            IdxData::I32(vec![0, 1, -2, 3, i32::MIN, i32::MAX]),
+            IdxData::F32(vec![0.0, 1.5, -2.0, 3.0, f32::MIN, f32::MAX]),
+            IdxData::F64(vec![0.0, 1.5, -2.0, 3.0, f64::MIN, f64::MAX]),
+        ];
+        for d in data {
+            let idx = Idx::new(vec![2, 1, 3], d).unwrap();
+            assert_eq!(roundtrip(&idx), idx);
+        }
+    }
+
+    #[test]
+    fn mnist_label_header_works() {
+        let bytes = [0, 0, 0x08, 1, 0, 0, 0, 3, 7, 2, 1];
+        let idx = Idx::read_from(&bytes[..]).unwrap();
+        assert_eq!(idx.dims(), [3]);
+        assert_eq!(idx.data(), &IdxData::U8(vec![7, 2, 1]));
+        assert_eq!(idx.to_matrix().into_vec(), [[7.0], [2.0], [1.0]]);
+    }
+
+    #[test]
+    fn overflowing_sizes_are_rejected() {
+        let huge = usize::MAX / 2 + 1;
+        assert!(Idx::new(vec![huge, 2], IdxData::U8(Vec::new())).is_err());
+
+        // 2^31 x 2^31 x 2^2 f64 elements (the byte size overflows on 64-bit targets)
+        let bytes = [
+            0, 0, 0x0E, 3, 128, 0, 0, 0, 128, 0, 0, 0, 0, 0, 0, 4, 1, 2, 3,
+        ];
+        assert!(Idx::read_from(&bytes[..]).is_err());
+    }
+
+    #[test]
+    fn matrix_conversion_works() {
+        let m = Matrix::from(vec![vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]);
+        let idx = Idx::from_matrix(&m);
+        assert_eq!(idx.dims(), [2, 3]);
+        assert_eq!(roundtrip(&idx).to_matrix(), m);
+
+        let idx = Idx::new(vec![2, 1, 2], IdxData::U8(vec![1, 2, 3, 4])).unwrap();
+        assert_eq!(idx.to_matrix().into_vec(), [[1.0, 2.0], [3.0, 4.0]]);
+    }
+
+    #[test]
+    fn reader_works() {
+        use std::fs;
+        use test_util::TempDir;
+
+        let dir = TempDir::new("dlfs-idx");
+        let path = dir.join("a.idx");
+        let idx = Idx::new(vec![3, 2], IdxData::I16(vec![1, -2, 3, -4, 5, -6])).unwrap();
+        idx.save(&path).unwrap();
+
+        let reader = IdxReader::open(&path).unwrap();
+        assert_eq!(reader.dims(), [3, 2]);
+        assert_eq!(reader.element_type(), ElementType::I16);
+        assert_eq!((reader.len(), reader.item_size()), (3, 2));
+        assert_eq!(reader.read_item(2).unwrap(), IdxData::I16(vec![5, -6]));
+        assert_eq!(
+            reader.read_items(0, 2).unwrap(),
+            IdxData::I16(vec![1, -2, 3, -4])
+        );
+
+        assert!(reader.read_items(2, 2).is_err());
+        assert!(reader.read_items(1, usize::MAX).is_err());
+        assert!(reader.read_items(3, 0).is_ok());
+
+        fs::write(&path, [0, 0, 0x08, 1, 0, 0, 0, 3, 7, 2]).unwrap();
+        assert!(IdxReader::open(&path).is_err());
+
+        // Two i16 elements followed by a partial one
+        fs::write(&path, [0, 0, 0x0B, 1, 0, 0, 0, 2, 0, 1, 0, 2, 0]).unwrap();
+        assert!(IdxReader::open(&path).is_err());
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        let error = |bytes: &[u8]| Idx::read_from(bytes).unwrap_err();
+        match error(&[1, 0, 0x08, 0]) {
+            Error::InvalidMagicNumber(_) => {}
+            e => panic!("{:?}", e),
+        }
+        match error(&[0, 0, 0x0A, 0]) {
+            Error::UnknownElementType(0x0A) => {}
+            e => panic!("{:?}", e),
+        }
+        match error(&[0, 0, 0x0B, 1, 0, 0, 0, 2, 0, 1, 0]) {
+            Error::SizeMismatch {
+                expected: 2,
+                actual: 1,
+            } => {}
+            e => panic!("{:?}", e),
+        }
+        match error(&[0, 0, 0x08, 2, 0, 0]) {
+            Error::Io(_) => {}
+            e => panic!("{:?}", e),
+        }
+        assert!(Idx::new(vec![2, 2], IdxData::U8(vec![0; 3])).is_err());
+    }
+}
@@ -0,0 +1,446 @@
+use rand::distributions::StandardNormal;
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::fmt::Debug;
+
+use super::Image;
+
+// Pixels that come from outside of the source image are filled with zeros
+pub trait Transform: Debug + Send + Sync {
+    fn apply(&self, image: &Image, rng: &mut StdRng) -> Image;
+}
+impl<T: Transform + ?Sized> Transform for Box<T> {
+    fn apply(&self, image: &Image, rng: &mut StdRng) -> Image {
+        (**self).apply(image, rng)
+    }
+}
+
+// Applies the transforms in order
+#[derive(Debug, Default)]
+pub struct Compose(pub Vec<Box<dyn Transform>>);
+impl Compose {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn then<T: Transform + 'static>(mut self, transform: T) -> Self {
+        self.0.push(Box::new(transform));
+        self
+    }
+}
+impl Transform for Compose {
+    fn apply(&self, image: &Image, rng: &mut StdRng) -> Image {
+        let mut image = image.clone();
+        for t in &self.0 {
+            image = t.apply(&image, rng);
+        }
+        image
+    }
+}
+
+// Pads the image with zeros and crops a `height` x `width` region at a random position
+#[derive(Debug, Clone)]
+pub struct RandomCrop {
+    pub height: usize,
+    pub width: usize,
+    pub padding: usize,
+}
+impl Transform for RandomCrop {
+    fn apply(&self, image: &Image, rng: &mut StdRng) -> Image {
+        let padded_height = image.height() + self.padding * 2;
+        let padded_width = image.width() + self.padding * 2;
+        assert!(self.height <= padded_height && self.width <= padded_width);
+
+        let top = rng.gen_range(0, padded_height - self.height + 1) as isize;
+        let left = rng.gen_range(0, padded_width - self.width + 1) as isize;
+        let padding = self.padding as isize;
+        remap(image, self.height, self.width, |c, y, x| {
+            pixel(
+                image,
+                c,
+                y as isize + top - padding,
+                x as isize + left - padding,
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HorizontalFlip {
+    pub probability: f64,
+}
+impl Default for HorizontalFlip {
+    fn default() -> Self {
+        HorizontalFlip { probability: 0.5 }
+    }
+}
+impl Transform for HorizontalFlip {
+    fn apply(&self, image: &Image, rng: &mut StdRng) -> Image {
+        if !rng.gen_bool(self.probability) {
+            return image.clone();
+        }
+        let images = image
+            .0
+            .iter()
+            .map(|c| {
+                c.iter()
+                    .map(|row| row.iter().rev().cloned().collect())
+                    .collect()
+            })
+            .collect();
+        Image(images)
+    }
+}
+
+// Rotates the image around its center by an angle in `[-max_degrees, max_degrees]`
+#[derive(Debug, Clone)]
+pub struct Rotation {
+    pub max_degrees: f64,
+}
+impl Transform for Rotation {
+    fn apply(&self, image: &Image, rng: &mut StdRng) -> Image {
+        let angle = if self.max_degrees > 0.0 {
+            rng.gen_range(-self.max_degrees, self.max_degrees)
+                .to_radians()
+        } else {
+            0.0
+        };
+        let (sin, cos) = angle.sin_cos();
+        let cy = (image.height() as f64 - 1.0) / 2.0;
+        let cx = (image.width() as f64 - 1.0) / 2.0;
+        remap_bilinear(image, |y, x| {
+            let (dy, dx) = (y - cy, x - cx);
+            (cy + dx * sin + dy * cos, cx + dx * cos - dy * sin)
+        })
+    }
+}
+
+// Shifts the image by up to `max_shift` pixels vertically and horizontally
+#[derive(Debug, Clone)]
+pub struct Translation {
+    pub max_shift: usize,
+}
+impl Transform for Translation {
+    fn apply(&self, image: &Image, rng: &mut StdRng) -> Image {
+        let max = self.max_shift as isize;
+        let dy = rng.gen_range(-max, max + 1);
+        let dx = rng.gen_range(-max, max + 1);
+        remap(image, image.height(), image.width(), |c, y, x| {
+            pixel(image, c, y as isize - dy, x as isize - dx)
+        })
+    }
+}
+
+// Simard et al., "Best Practices for Convolutional Neural Networks Applied to Visual Document Analysis"
+#[derive(Debug, Clone)]
+pub struct ElasticDistortion {
+    // The scale of the displacements (in pixels)
+    pub alpha: f64,
+
+    // The standard deviation of the Gaussian filter that smooths the displacement fields
+    pub sigma: f64,
+}
+impl Default for ElasticDistortion {
+    fn default() -> Self {
+        ElasticDistortion {
+            alpha: 8.0,
+            sigma: 3.0,
+        }
+    }
+}
+impl Transform for ElasticDistortion {
+    fn apply(&self, image: &Image, rng: &mut StdRng) -> Image {
+        let (height, width) = (image.height(), image.width());
+        if height == 0 || width == 0 {
+            return image.clone();
+        }
+        let mut field = || {
+            let field = (0..height)
+                .map(|_| (0..width).map(|_| rng.gen_range(-1.0, 1.0)).collect())
+                .collect::<Vec<Vec<f64>>>();
+            gaussian_blur(&field, self.sigma)
+        };
+        let dys = field();
+        let dxs = field();
+        remap_bilinear(image, |y, x| {
+            let (i, j) = (y as usize, x as usize);
+            (y + self.alpha * dys[i][j], x + self.alpha * dxs[i][j])
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GaussianNoise {
+    pub std: f64,
+}
+impl Transform for GaussianNoise {
+    fn apply(&self, image: &Image, rng: &mut StdRng) -> Image {
+        let images = image
+            .0
+            .iter()
+            .map(|c| {
+                c.iter()
+                    .map(|row| {
+                        row.iter()
+                            .map(|v| v + rng.sample(StandardNormal) * self.std)
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        Image(images)
+    }
+}
+
+// DeVries and Taylor, "Improved Regularization of Convolutional Neural Networks with Cutout".
+// Zeroes a `size` x `size` square centered at a random pixel (the square may stick out of the image).
+#[derive(Debug, Clone)]
+pub struct Cutout {
+    pub size: usize,
+}
+impl Transform for Cutout {
+    fn apply(&self, image: &Image, rng: &mut StdRng) -> Image {
+        if image.height() == 0 || image.width() == 0 {
+            return image.clone();
+        }
+        let cy = rng.gen_range(0, image.height()) as isize;
+        let cx = rng.gen_range(0, image.width()) as isize;
+        let top = cy - (self.size / 2) as isize;
+        let left = cx - (self.size / 2) as isize;
+        let size = self.size as isize;
+        let mut image = image.clone();
+        for c in &mut image.0 {
+            for (y, row) in c.iter_mut().enumerate() {
+                for (x, v) in row.iter_mut().enumerate() {
+                    let (y, x) = (y as isize, x as isize);
+                    if top <= y && y < top + size && left <= x && x < left + size {
+                        *v = 0.0;
+                    }
+                }
+            }
+        }
+        image
+    }
+}
+
+fn pixel(image: &Image, c: usize, y: isize, x: isize) -> f64 {
+    if y < 0 || x < 0 || y >= image.height() as isize || x >= image.width() as isize {
+        0.0
+    } else {
+        image.0[c][y as usize][x as usize]
+    }
+}
+
+// Builds a `height` x `width` image whose pixel `(c, y, x)` is `f(c, y, x)`
+pub(super) fn remap<F>(image: &Image, height: usize, width: usize, f: F) -> Image
+where
+    F: Fn(usize, usize, usize) -> f64,
+{
+    let images = (0..image.channels())
+        .map(|c| {
+            (0..height)
+                .map(|y| (0..width).map(|x| f(c, y, x)).collect())
+                .collect()
+        })
+        .collect();
+    Image(images)
+}
+
+// `f` maps a destination pixel to the (fractional) source position
+fn remap_bilinear<F>(image: &Image, f: F) -> Image
+where
+    F: Fn(f64, f64) -> (f64, f64),
+{
+    let (height, width) = (image.height(), image.width());
+    let positions = (0..height)
+        .map(|y| {
+            (0..width)
+                .map(|x| f(y as f64, x as f64))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    remap(image, height, width, |c, y, x| {
+        let (sy, sx) = positions[y][x];
+        let (y0, x0) = (sy.floor(), sx.floor());
+        let (wy, wx) = (sy - y0, sx - x0);
+        let (y0, x0) = (y0 as isize, x0 as isize);
+        pixel(image, c, y0, x0) * (1.0 - wy) * (1.0 - wx)
+            + pixel(image, c, y0, x0 + 1) * (1.0 - wy) * wx
+            + pixel(image, c, y0 + 1, x0) * wy * (1.0 - wx)
+            + pixel(image, c, y0 + 1, x0 + 1) * wy * wx
+    })
+}
+
+fn gaussian_blur(field: &[Vec<f64>], sigma: f64) -> Vec<Vec<f64>> {
+    if sigma <= 0.0 {
+        return field.to_vec();
+    }
+    let radius = (sigma * 3.0).ceil() as isize;
+    let kernel = (-radius..=radius)
+        .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect::<Vec<_>>();
+    let total = kernel.iter().sum::<f64>();
+    let kernel = kernel.into_iter().map(|k| k / total).collect::<Vec<_>>();
+
+    let (height, width) = (field.len() as isize, field[0].len() as isize);
+    let convolve = |get: &dyn Fn(isize) -> Option<f64>, center: isize| {
+        kernel
+            .iter()
+            .zip(-radius..=radius)
+            .filter_map(|(k, i)| get(center + i).map(|v| k * v))
+            .sum::<f64>()
+    };
+    let horizontal = field
+        .iter()
+        .map(|row| {
+            (0..width)
+                .map(|x| {
+                    let get = |x: isize| row.get(x as usize).filter(|_| x >= 0).cloned();
+                    convolve(&get, x)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    (0..height)
+        .map(|y| {
+            (0..width as usize)
+                .map(|x| {
+                    let get =
+                        |y: isize| horizontal.get(y as usize).filter(|_| y >= 0).map(|r| r[x]);
+                    convolve(&get, y)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn gradient_image() -> Image {
+        let channel = (0..4)
+            .map(|y| (0..5).map(|x| (y * 5 + x) as f64).collect())
+            .collect::<Vec<_>>();
+        Image(vec![channel.clone(), channel])
+    }
+
+    fn rng() -> StdRng {
+        StdRng::seed_from_u64(0)
+    }
+
+    #[test]
+    fn horizontal_flip_works() {
+        let image = gradient_image();
+        let flipped = HorizontalFlip { probability: 1.0 }.apply(&image, &mut rng());
+        assert_eq!(flipped.0[1][2], [14.0, 13.0, 12.0, 11.0, 10.0]);
+        let same = HorizontalFlip { probability: 0.0 }.apply(&image, &mut rng());
+        assert_eq!(same.0, image.0);
+    }
+
+    #[test]
+    fn shifts_work() {
+        let image = gradient_image();
+        let mut rng = rng();
+        for _ in 0..10 {
+            let cropped = RandomCrop {
+                height: 3,
+                width: 3,
+                padding: 1,
+            }
+            .apply(&image, &mut rng);
+            assert_eq!((cropped.height(), cropped.width()), (3, 3));
+
+            // The shifted content keeps its horizontal and vertical steps
+            let shifted = Translation { max_shift: 2 }.apply(&image, &mut rng);
+            assert_eq!((shifted.height(), shifted.width()), (4, 5));
+            let (y, x) = (0..4)
+                .flat_map(|y| (0..5).map(move |x| (y, x)))
+                .find(|&(y, x)| shifted.0[0][y][x] != 0.0)
+                .unwrap();
+            let v = shifted.0[0][y][x];
+            if x + 1 < 5 && v % 5.0 != 4.0 {
+                assert_eq!(shifted.0[0][y][x + 1], v + 1.0);
+            }
+        }
+
+        let identity = Translation { max_shift: 0 }.apply(&image, &mut rng);
+        assert_eq!(identity.0, image.0);
+        let identity = RandomCrop {
+            height: 4,
+            width: 5,
+            padding: 0,
+        }
+        .apply(&image, &mut rng);
+        assert_eq!(identity.0, image.0);
+    }
+
+    #[test]
+    fn rotation_works() {
+        let image = gradient_image();
+        let identity = Rotation { max_degrees: 0.0 }.apply(&image, &mut rng());
+        assert_eq!(identity.0, image.0);
+
+        let rotated = Rotation { max_degrees: 30.0 }.apply(&image, &mut rng());
+        assert_eq!((rotated.height(), rotated.width()), (4, 5));
+        assert_ne!(rotated.0, image.0);
+    }
+
+    #[test]
+    fn elastic_distortion_works() {
+        let image = gradient_image();
+        let options = ElasticDistortion {
+            alpha: 0.0,
+            sigma: 1.0,
+        };
+        assert_eq!(options.apply(&image, &mut rng()).0, image.0);
+        let distorted = ElasticDistortion::default().apply(&image, &mut rng());
+        assert_ne!(distorted.0, image.0);
+
+        let blurred = gaussian_blur(&vec![vec![0.0, 0.0, 3.0, 0.0, 0.0]; 7], 1.0);
+        assert!((blurred[3].iter().sum::<f64>() - 3.0).abs() < 0.1);
+        assert!(blurred[3][1] > 0.0 && blurred[3][2] < 3.0);
+    }
+
+    #[test]
+    fn cutout_and_noise_work() {
+        let image = Image(vec![vec![vec![1.0; 6]; 6]]);
+        let mut rng = rng();
+        let cut = Cutout { size: 2 }.apply(&image, &mut rng);
+        let zeros = cut.0[0].iter().flatten().filter(|&&v| v == 0.0).count();
+        assert!((1..=4).contains(&zeros), "{}", zeros);
+
+        let noisy = GaussianNoise { std: 0.1 }.apply(&image, &mut rng);
+        let mean = noisy.0[0].iter().flatten().sum::<f64>() / 36.0;
+        assert!((mean - 1.0).abs() < 0.1);
+        assert_ne!(noisy.0, image.0);
+    }
+
+    #[test]
+    fn compose_is_reproducible() {
+        let transform = Compose::new()
+            .then(RandomCrop {
+                height: 4,
+                width: 5,
+                padding: 1,
+            })
+            .then(Rotation { max_degrees: 10.0 })
+            .then(Cutout { size: 2 });
+        let image = gradient_image();
+        let a = transform.apply(&image, &mut rng());
+        let b = transform.apply(&image, &mut rng());
+        assert_eq!(a.0, b.0);
+    }
+
+    #[test]
+    fn empty_images_work() {
+        let transform = Compose::new()
+            .then(ElasticDistortion::default())
+            .then(Cutout { size: 2 });
+        assert!(format!("{:?}", transform).starts_with("Compose"));
+        for image in &[Image::new(1, 0, 0), Image::new(2, 3, 0), Image(Vec::new())] {
+            assert_eq!(&transform.apply(image, &mut rng()), image);
+        }
+    }
+}
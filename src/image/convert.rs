@@ -0,0 +1,296 @@
+use super::augment::remap;
+use super::Image;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Nearest,
+
+    // Only looks at the 4 nearest source pixels, so shrinking by more than 2x aliases
+    // (use `Area` for that)
+    Bilinear,
+
+    // Averages the source pixels covered by each destination pixel (weighted by the overlap).
+    // Suitable for downscaling (e.g., 280x280 to 28x28).
+    Area,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+    Zero,
+
+    // Mirrors the image without repeating the edge pixels (e.g., `c b | a b c d | c b`)
+    Reflect,
+}
+
+impl Image {
+    // Pixels are treated as squares, so the corners of the image are aligned (not the pixel centers)
+    pub fn resize(&self, height: usize, width: usize, interpolation: Interpolation) -> Self {
+        assert!(height > 0 && width > 0);
+        assert!(
+            self.height() > 0 && self.width() > 0,
+            "Cannot resize an empty image: {:?}",
+            self.shape()
+        );
+        let scale_y = self.height() as f64 / height as f64;
+        let scale_x = self.width() as f64 / width as f64;
+        let (max_y, max_x) = (self.height() - 1, self.width() - 1);
+        match interpolation {
+            Interpolation::Nearest => remap(self, height, width, |c, y, x| {
+                let sy = ((y as f64 + 0.5) * scale_y) as usize;
+                let sx = ((x as f64 + 0.5) * scale_x) as usize;
+                self.0[c][sy.min(max_y)][sx.min(max_x)]
+            }),
+            Interpolation::Bilinear => remap(self, height, width, |c, y, x| {
+                let sy = ((y as f64 + 0.5) * scale_y - 0.5).max(0.0);
+                let sx = ((x as f64 + 0.5) * scale_x - 0.5).max(0.0);
+                let (y0, x0) = ((sy as usize).min(max_y), (sx as usize).min(max_x));
+                let (y1, x1) = ((y0 + 1).min(max_y), (x0 + 1).min(max_x));
+                let (wy, wx) = (sy - y0 as f64, sx - x0 as f64);
+                let p = &self.0[c];
+                p[y0][x0] * (1.0 - wy) * (1.0 - wx)
+                    + p[y0][x1] * (1.0 - wy) * wx
+                    + p[y1][x0] * wy * (1.0 - wx)
+                    + p[y1][x1] * wy * wx
+            }),
+            Interpolation::Area => {
+                let ys = area_weights(self.height(), height);
+                let xs = area_weights(self.width(), width);
+                remap(self, height, width, |c, y, x| {
+                    let p = &self.0[c];
+                    ys[y]
+                        .iter()
+                        .map(|&(sy, wy)| {
+                            xs[x].iter().map(|&(sx, wx)| p[sy][sx] * wx).sum::<f64>() * wy
+                        })
+                        .sum()
+                })
+            }
+        }
+    }
+
+    pub fn crop(&self, top: usize, left: usize, height: usize, width: usize) -> Self {
+        let bottom = top.checked_add(height);
+        let right = left.checked_add(width);
+        assert!(
+            bottom.is_some_and(|b| b <= self.height()) && right.is_some_and(|r| r <= self.width()),
+            "Crop ({}, {}, {}, {}) is out of the image ({} x {})",
+            top,
+            left,
+            height,
+            width,
+            self.height(),
+            self.width()
+        );
+        remap(self, height, width, |c, y, x| self.0[c][top + y][left + x])
+    }
+
+    pub fn center_crop(&self, height: usize, width: usize) -> Self {
+        assert!(height <= self.height() && width <= self.width());
+        let top = (self.height() - height) / 2;
+        let left = (self.width() - width) / 2;
+        self.crop(top, left, height, width)
+    }
+
+    // Adds `padding` pixels to every side
+    pub fn pad(&self, padding: usize, mode: Padding) -> Self {
+        let (height, width) = (self.height(), self.width());
+        assert!(
+            mode == Padding::Zero || padding == 0 || (height > 0 && width > 0),
+            "Cannot reflect an empty image: {:?}",
+            self.shape()
+        );
+        remap(
+            self,
+            height + padding * 2,
+            width + padding * 2,
+            |c, y, x| {
+                let y = y as isize - padding as isize;
+                let x = x as isize - padding as isize;
+                match mode {
+                    Padding::Zero => {
+                        if y < 0 || x < 0 || y >= height as isize || x >= width as isize {
+                            0.0
+                        } else {
+                            self.0[c][y as usize][x as usize]
+                        }
+                    }
+                    Padding::Reflect => self.0[c][reflect(y, height)][reflect(x, width)],
+                }
+            },
+        )
+    }
+
+    // RGB (and RGBA, ignoring the alpha) images are converted with the ITU-R BT.601 luma weights.
+    // Gray + alpha images lose the alpha channel.
+    pub fn to_grayscale(&self) -> Self {
+        match self.channels() {
+            1 | 2 => Image(vec![self.0[0].clone()]),
+            3 | 4 => {
+                let (r, g, b) = (&self.0[0], &self.0[1], &self.0[2]);
+                let gray = (0..self.height())
+                    .map(|y| {
+                        (0..self.width())
+                            .map(|x| 0.299 * r[y][x] + 0.587 * g[y][x] + 0.114 * b[y][x])
+                            .collect()
+                    })
+                    .collect();
+                Image(vec![gray])
+            }
+            n => panic!("Unsupported number of channels: {}", n),
+        }
+    }
+
+    // Gray images are copied into each channel, and the alpha channel (if any) is dropped
+    pub fn to_rgb(&self) -> Self {
+        match self.channels() {
+            1 | 2 => Image(vec![self.0[0].clone(); 3]),
+            3 | 4 => Image(self.0[..3].to_vec()),
+            n => panic!("Unsupported number of channels: {}", n),
+        }
+    }
+
+    // `1 - v` for every pixel (e.g., for dark digits on a white background)
+    pub fn invert(&self) -> Self {
+        remap(self, self.height(), self.width(), |c, y, x| {
+            1.0 - self.0[c][y][x]
+        })
+    }
+}
+
+// For each destination index, the source indices it covers and their (normalized) overlaps
+fn area_weights(src: usize, dst: usize) -> Vec<Vec<(usize, f64)>> {
+    let scale = src as f64 / dst as f64;
+    (0..dst)
+        .map(|i| {
+            let (start, end) = (i as f64 * scale, (i + 1) as f64 * scale);
+            let last = (end.ceil() as usize).min(src);
+            (start.floor() as usize..last)
+                .map(|j| {
+                    let overlap = end.min((j + 1) as f64) - start.max(j as f64);
+                    (j, overlap / scale)
+                })
+                .filter(|&(_, w)| w > 0.0)
+                .collect()
+        })
+        .collect()
+}
+
+fn reflect(i: isize, size: usize) -> usize {
+    if size == 1 {
+        return 0;
+    }
+    let period = 2 * (size as isize - 1);
+    let i = i.rem_euclid(period);
+    if i < size as isize {
+        i as usize
+    } else {
+        (period - i) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(rows: Vec<Vec<f64>>) -> Image {
+        Image(vec![rows])
+    }
+
+    #[test]
+    fn resize_works() {
+        let image = gray(vec![vec![0.0, 1.0], vec![2.0, 3.0]]);
+        let nearest = image.resize(4, 4, Interpolation::Nearest);
+        assert_eq!(nearest.0[0][1], [0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(nearest.0[0][2], [2.0, 2.0, 3.0, 3.0]);
+
+        let bilinear = image.resize(4, 4, Interpolation::Bilinear);
+        assert_eq!(bilinear.0[0][0], [0.0, 0.25, 0.75, 1.0]);
+        assert_eq!(bilinear.0[0][2], [1.5, 1.75, 2.25, 2.5]);
+
+        // Downsampling by 2 averages the 2 x 2 blocks
+        let image = gray(
+            (0..4)
+                .map(|y| (0..4).map(|x| (y * 4 + x) as f64).collect())
+                .collect(),
+        );
+        let small = image.resize(2, 2, Interpolation::Bilinear);
+        assert_eq!(small.0[0], [[2.5, 4.5], [10.5, 12.5]]);
+        assert_eq!(
+            image.resize(1, 3, Interpolation::Nearest).0[0],
+            [[8.0, 10.0, 11.0]]
+        );
+    }
+
+    #[test]
+    fn area_resize_works() {
+        // A 40 x 40 checkerboard averages to flat gray, which bilinear sampling misses
+        let board = gray(
+            (0..40)
+                .map(|y| (0..40).map(|x| ((x + y) % 2) as f64).collect())
+                .collect(),
+        );
+        let small = board.resize(4, 4, Interpolation::Area);
+        assert!(small.channel(0).unwrap().all(|&v| (v - 0.5).abs() < 1e-12));
+
+        // Non-integer scales weight partially covered pixels
+        let row = gray(vec![vec![0.0, 3.0, 6.0]]);
+        let small = row.resize(1, 2, Interpolation::Area);
+        assert!((small.0[0][0][0] - 1.0).abs() < 1e-12);
+        assert!((small.0[0][0][1] - 5.0).abs() < 1e-12);
+        assert_eq!(
+            row.resize(1, 6, Interpolation::Area).0[0],
+            [[0.0, 0.0, 3.0, 3.0, 6.0, 6.0]]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "empty image")]
+    fn resizing_empty_image_panics() {
+        Image::new(1, 0, 0).resize(2, 2, Interpolation::Bilinear);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of the image")]
+    fn overflowing_crop_panics() {
+        Image::new(1, 2, 2).crop(1, 0, usize::MAX, 1);
+    }
+
+    #[test]
+    fn crop_and_pad_work() {
+        let image = gray(
+            (0..3)
+                .map(|y| (0..4).map(|x| (y * 4 + x) as f64).collect())
+                .collect(),
+        );
+        assert_eq!(image.crop(1, 2, 2, 2).0[0], [[6.0, 7.0], [10.0, 11.0]]);
+        assert_eq!(image.center_crop(1, 2).0[0], [[5.0, 6.0]]);
+
+        let padded = image.pad(2, Padding::Zero);
+        assert_eq!((padded.height(), padded.width()), (7, 8));
+        assert_eq!(padded.0[0][2], [0.0, 0.0, 0.0, 1.0, 2.0, 3.0, 0.0, 0.0]);
+        assert_eq!(padded.0[0][0], [0.0; 8]);
+
+        let padded = image.pad(2, Padding::Reflect);
+        assert_eq!(padded.0[0][2], [2.0, 1.0, 0.0, 1.0, 2.0, 3.0, 2.0, 1.0]);
+        assert_eq!(padded.0[0][0][2..6], [8.0, 9.0, 10.0, 11.0]);
+        assert_eq!(padded.0[0][6][2..6], [0.0, 1.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn channel_conversion_works() {
+        let rgb = Image(vec![
+            vec![vec![1.0, 0.0]],
+            vec![vec![1.0, 0.0]],
+            vec![vec![1.0, 1.0]],
+        ]);
+        let gray = rgb.to_grayscale();
+        assert_eq!(gray.channels(), 1);
+        assert!((gray.0[0][0][0] - 1.0).abs() < 1e-12);
+        assert!((gray.0[0][0][1] - 0.114).abs() < 1e-12);
+
+        let back = gray.to_rgb();
+        assert_eq!(back.channels(), 3);
+        assert_eq!(back.0[2], gray.0[0]);
+        assert_eq!(rgb.invert().0[2], [[0.0, 0.0]]);
+    }
+}
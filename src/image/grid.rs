@@ -0,0 +1,184 @@
+use std::io;
+
+use super::Image;
+use matrix::Matrix;
+
+#[derive(Debug, Clone)]
+pub struct GridOptions {
+    // The number of tiles per row (`0` means `ceil(sqrt(tiles))`)
+    pub columns: usize,
+
+    // The width of the lines between and around the tiles
+    pub border: usize,
+    pub border_value: f64,
+
+    // Scales each tile into `[0, 1]` independently (e.g., for weights)
+    pub normalize: bool,
+}
+impl Default for GridOptions {
+    fn default() -> Self {
+        GridOptions {
+            columns: 0,
+            border: 1,
+            border_value: 1.0,
+            normalize: true,
+        }
+    }
+}
+
+// Arranges same-shaped images into a single image (e.g., `grid(&images, &options)?.save("a.png")`).
+// No images result in an empty image.
+pub fn grid(images: &[Image], options: &GridOptions) -> io::Result<Image> {
+    if images.is_empty() {
+        return Ok(Image(Vec::new()));
+    }
+    let (channels, height, width) = images[0].shape();
+    for (i, image) in images.iter().enumerate() {
+        image.validate()?;
+        if image.shape() != (channels, height, width) {
+            let message = format!(
+                "Image {} has the shape {:?}, but image 0 has {:?}",
+                i,
+                image.shape(),
+                (channels, height, width)
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+    }
+
+    let columns = if options.columns == 0 {
+        (images.len() as f64).sqrt().ceil() as usize
+    } else {
+        options.columns
+    };
+    let rows = images.len().div_ceil(columns);
+    let border = options.border;
+    let mut out = vec![
+        vec![
+            vec![options.border_value; columns * (width + border) + border];
+            rows * (height + border) + border
+        ];
+        channels
+    ];
+    for (i, image) in images.iter().enumerate() {
+        let top = border + (i / columns) * (height + border);
+        let left = border + (i % columns) * (width + border);
+        let (min, max) = if options.normalize {
+            value_range(image)
+        } else {
+            (0.0, 1.0)
+        };
+        for (c, channel) in image.0.iter().enumerate() {
+            for (y, row) in channel.iter().enumerate() {
+                for (x, &v) in row.iter().enumerate() {
+                    out[c][top + y][left + x] = if max > min {
+                        (v - min) / (max - min)
+                    } else {
+                        0.5
+                    };
+                }
+            }
+        }
+    }
+    Ok(Image(out))
+}
+
+// Each row of `m` is a flattened (channel, height, width) image (e.g., a batch of MNIST inputs)
+pub fn grid_rows(
+    m: &Matrix,
+    shape: (usize, usize, usize),
+    options: &GridOptions,
+) -> io::Result<Image> {
+    let images = (0..m.rows())
+        .map(|i| Image::from_matrix_row(m, i, shape))
+        .collect::<io::Result<Vec<_>>>()?;
+    grid(&images, options)
+}
+
+// Each column of `m` is a flattened image (e.g., the filters of the first `AffineLayer::w`)
+pub fn grid_columns(
+    m: &Matrix,
+    shape: (usize, usize, usize),
+    options: &GridOptions,
+) -> io::Result<Image> {
+    grid_rows(&m.transpose(), shape, options)
+}
+
+fn value_range(image: &Image) -> (f64, f64) {
+    image
+        .0
+        .iter()
+        .flat_map(|c| c.iter().flat_map(|row| row.iter()))
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &v| {
+            (min.min(v), max.max(v))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_works() {
+        let images = (0..5)
+            .map(|i| Image(vec![vec![vec![i as f64, 2.0 * i as f64]; 2]]))
+            .collect::<Vec<_>>();
+        let g = grid(&images, &Default::default()).unwrap();
+
+        // 3 x 2 tiles of 2 x 2 pixels with 1-pixel borders
+        assert_eq!((g.channels(), g.height(), g.width()), (1, 7, 10));
+        assert_eq!(g.0[0][0], vec![1.0; 10]);
+        assert_eq!(g.0[0][1][..4], [1.0, 0.5, 0.5, 1.0]);
+        assert_eq!(g.0[0][4][..4], [1.0, 0.0, 1.0, 1.0]);
+        assert_eq!(g.0[0][4][4..], [0.0, 1.0, 1.0, 1.0, 1.0, 1.0]);
+
+        let options = GridOptions {
+            columns: 5,
+            border: 0,
+            border_value: 0.0,
+            normalize: false,
+        };
+        let g = grid(&images, &options).unwrap();
+        assert_eq!((g.height(), g.width()), (2, 10));
+        assert_eq!(g.0[0][1][8..], [4.0, 8.0]);
+    }
+
+    #[test]
+    fn grid_columns_works() {
+        // Two 1 x 2 x 3 filters
+        let w = Matrix::from(vec![
+            vec![0.0, 5.0],
+            vec![1.0, 4.0],
+            vec![2.0, 3.0],
+            vec![3.0, 2.0],
+            vec![4.0, 1.0],
+            vec![5.0, 0.0],
+        ]);
+        let options = GridOptions {
+            border: 0,
+            ..Default::default()
+        };
+        let g = grid_columns(&w, (1, 2, 3), &options).unwrap();
+        assert_eq!((g.height(), g.width()), (2, 6));
+        assert_eq!(g.0[0][0], [0.0, 0.2, 0.4, 1.0, 0.8, 0.6]);
+        assert_eq!(g.0[0][1], [0.6, 0.8, 1.0, 0.4, 0.2, 0.0]);
+        assert!(grid_columns(&w, (1, 2, 2), &options).is_err());
+    }
+
+    #[test]
+    fn invalid_inputs_work() {
+        let options = GridOptions::default();
+        assert_eq!(grid(&[], &options).unwrap().shape(), (0, 0, 0));
+        assert_eq!(
+            grid_rows(&Matrix::new(0, 4), (1, 2, 2), &options)
+                .unwrap()
+                .shape(),
+            (0, 0, 0)
+        );
+
+        let mut ragged = Image::new(1, 2, 2);
+        ragged.0[0][1].pop();
+        assert!(grid(&[Image::new(1, 2, 2), ragged], &options).is_err());
+        assert!(grid(&[Image::new(1, 2, 2), Image::new(3, 2, 2)], &options).is_err());
+    }
+}
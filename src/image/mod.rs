@@ -0,0 +1,378 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use data::MnistEntry;
+use matrix::Matrix;
+
+pub use self::augment::{
+    Compose, Cutout, ElasticDistortion, GaussianNoise, HorizontalFlip, RandomCrop, Rotation,
+    Transform, Translation,
+};
+pub use self::convert::{Interpolation, Padding};
+pub use self::grid::{grid, grid_columns, grid_rows, GridOptions};
+
+mod augment;
+mod convert;
+mod grid;
+mod png;
+mod pnm;
+
+// [channel][height][width]
+//
+// The field is public for compatibility; prefer the constructors below,
+// which guarantee that every channel and row has the same size.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image(pub Vec<Vec<Vec<f64>>>);
+impl Image {
+    // A black image
+    pub fn new(channels: usize, height: usize, width: usize) -> Self {
+        Image(vec![vec![vec![0.0; width]; height]; channels])
+    }
+
+    // `values`: flattened (channels, height, width) values.
+    // A 0-channel image cannot keep its height and width, so only (0, 0, 0) is accepted for it.
+    pub fn from_slice(
+        channels: usize,
+        height: usize,
+        width: usize,
+        values: &[f64],
+    ) -> io::Result<Self> {
+        if channels == 0 && (height != 0 || width != 0) {
+            let message = format!(
+                "An image of {} x {} needs at least one channel",
+                height, width
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        let count = channels
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(width));
+        if count != Some(values.len()) {
+            let message = format!(
+                "{} values cannot be reshaped to ({}, {}, {})",
+                values.len(),
+                channels,
+                height,
+                width
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        if height * width == 0 {
+            return Ok(Self::new(channels, height, width));
+        }
+        let image = values
+            .chunks(height * width)
+            .map(|c| c.chunks(width).map(Vec::from).collect())
+            .collect();
+        Ok(Image(image))
+    }
+
+    // Checks that the nested vectors are not ragged
+    pub fn from_nested(values: Vec<Vec<Vec<f64>>>) -> io::Result<Self> {
+        let image = Image(values);
+        image.validate()?;
+        Ok(image)
+    }
+
+    // A (1, 28, 28) image
+    pub fn from_mnist(entry: &MnistEntry) -> io::Result<Self> {
+        Self::from_slice(1, 28, 28, entry.image)
+    }
+
+    // The `row`-th row of `m` as a flattened (channels, height, width) image
+    pub fn from_matrix_row(
+        m: &Matrix,
+        row: usize,
+        shape: (usize, usize, usize),
+    ) -> io::Result<Self> {
+        if row >= m.rows() {
+            let message = format!("Row {} is out of range (rows={})", row, m.rows());
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        Self::from_slice(shape.0, shape.1, shape.2, m.row_slice(row))
+    }
+
+    pub fn validate(&self) -> io::Result<()> {
+        let (height, width) = (self.height(), self.width());
+        for (c, channel) in self.0.iter().enumerate() {
+            if channel.len() != height {
+                let message = format!(
+                    "Channel {} has {} rows, but channel 0 has {}",
+                    c,
+                    channel.len(),
+                    height
+                );
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+            if let Some((y, row)) = channel.iter().enumerate().find(|(_, r)| r.len() != width) {
+                let message = format!(
+                    "Row {} of channel {} has {} pixels, but {} are expected",
+                    y,
+                    c,
+                    row.len(),
+                    width
+                );
+                return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn channels(&self) -> usize {
+        self.0.len()
+    }
+
+    // `0` if the image has no channels (or rows)
+    pub fn height(&self) -> usize {
+        self.0.first().map_or(0, Vec::len)
+    }
+
+    pub fn width(&self) -> usize {
+        self.0.first().and_then(|c| c.first()).map_or(0, Vec::len)
+    }
+
+    // (channels, height, width)
+    pub fn shape(&self) -> (usize, usize, usize) {
+        (self.channels(), self.height(), self.width())
+    }
+
+    pub fn get(&self, channel: usize, y: usize, x: usize) -> Option<f64> {
+        self.0.get(channel)?.get(y)?.get(x).cloned()
+    }
+
+    pub fn get_mut(&mut self, channel: usize, y: usize, x: usize) -> Option<&mut f64> {
+        self.0.get_mut(channel)?.get_mut(y)?.get_mut(x)
+    }
+
+    // The pixels of a channel in row-major order
+    pub fn channel(&self, channel: usize) -> Option<impl Iterator<Item = &f64>> {
+        let rows = self.0.get(channel)?;
+        Some(rows.iter().flat_map(|row| row.iter()))
+    }
+
+    pub fn channel_mut(&mut self, channel: usize) -> Option<impl Iterator<Item = &mut f64>> {
+        let rows = self.0.get_mut(channel)?;
+        Some(rows.iter_mut().flat_map(|row| row.iter_mut()))
+    }
+
+    // The inverse of `from_slice`
+    pub fn into_vec(self) -> Vec<f64> {
+        self.0.into_iter().flatten().flatten().collect()
+    }
+
+    // `pixels`: (channels, height, width) bytes which are scaled into `[0, 1]`
+    pub fn from_u8(channels: usize, height: usize, width: usize, pixels: &[u8]) -> Self {
+        assert_eq!(pixels.len(), channels * height * width);
+        let image = pixels
+            .chunks(height * width)
+            .map(|c| {
+                c.chunks(width)
+                    .map(|row| row.iter().map(|&v| f64::from(v) / 255.0).collect())
+                    .collect()
+            })
+            .collect();
+        Image(image)
+    }
+
+    // The inverse of `from_u8` (values out of `[0, 1]` are clamped)
+    pub fn to_u8(&self) -> Vec<u8> {
+        self.0
+            .iter()
+            .flat_map(|c| c.iter().flat_map(|row| row.iter().map(|&v| to_u8(v))))
+            .collect()
+    }
+
+    // Detects the format (PGM/PPM or PNG) from the content
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+        if bytes.starts_with(&png::SIGNATURE) {
+            Self::read_png(&bytes[..])
+        } else {
+            Self::read_pnm(&bytes[..])
+        }
+    }
+
+    // Chooses the format from the extension: ".png", or ".pgm", ".ppm" and ".pnm"
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        let png = match extension.as_ref().map(|e| &e[..]) {
+            Some("png") => true,
+            Some("pgm") | Some("ppm") | Some("pnm") => false,
+            _ => {
+                let message = format!("Unknown image file extension: {:?}", path);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            }
+        };
+
+        // Checks the image before creating (or truncating) the file
+        if png {
+            self.png_color_type()?;
+        } else {
+            self.pnm_magic()?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        if png {
+            self.write_png(&mut writer)?;
+        } else {
+            self.write_pnm(&mut writer)?;
+        }
+        writer.flush()
+    }
+
+    // Builds an image from interleaved (height, width, channels) values
+    fn from_interleaved(channels: usize, height: usize, width: usize, values: &[f64]) -> Self {
+        assert_eq!(values.len(), channels * height * width);
+        let image = (0..channels)
+            .map(|c| {
+                (0..height)
+                    .map(|y| {
+                        (0..width)
+                            .map(|x| values[(y * width + x) * channels + c])
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        Image(image)
+    }
+
+    fn to_interleaved_u8(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.channels() * self.height() * self.width());
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                bytes.extend(self.0.iter().map(|c| to_u8(c[y][x])));
+            }
+        }
+        bytes
+    }
+}
+
+fn to_u8(v: f64) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// The decoders refuse larger images (`f64` values take 8 bytes each, i.e., 512 MiB at most)
+const MAX_VALUES: usize = 1 << 26;
+
+// Bounds each side too, so that a huge height cannot allocate rows of a narrow image
+const MAX_SIDE: usize = 1 << 16;
+
+// `channels * height * width` of an image described by a file header.
+// Empty images are rejected because neither format can represent them.
+fn value_count(channels: usize, height: usize, width: usize) -> io::Result<usize> {
+    if height == 0 || width == 0 {
+        return invalid_data(format!("Empty image: {} x {}", height, width));
+    }
+    if height > MAX_SIDE || width > MAX_SIDE {
+        return invalid_data(format!("Too large image: {} x {}", height, width));
+    }
+    channels
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(width))
+        .filter(|&n| n <= MAX_VALUES)
+        .map_or_else(
+            || {
+                invalid_data(format!(
+                    "Too large image: (channels, height, width) = ({}, {}, {})",
+                    channels, height, width
+                ))
+            },
+            Ok,
+        )
+}
+
+fn invalid_data<T>(message: String) -> io::Result<T> {
+    Err(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_util::TempDir;
+
+    #[test]
+    fn constructors_work() {
+        let values = (0..12).map(f64::from).collect::<Vec<_>>();
+        let image = Image::from_slice(2, 3, 2, &values).unwrap();
+        assert_eq!(image.shape(), (2, 3, 2));
+        assert_eq!(image.0[1][2], [10.0, 11.0]);
+        assert_eq!(image.channel(1).unwrap().count(), 6);
+        assert_eq!(image.clone().into_vec(), values);
+        assert!(Image::from_slice(2, 3, 3, &values).is_err());
+        assert!(Image::from_slice(usize::MAX, 2, 1, &values).is_err());
+        assert!(Image::from_slice(0, 3, 2, &[]).is_err());
+        assert_eq!(Image::from_slice(0, 0, 0, &[]).unwrap().shape(), (0, 0, 0));
+        assert_eq!(Image::from_slice(2, 0, 3, &[]).unwrap().shape(), (2, 0, 0));
+
+        let m = Matrix::from(vec![values.clone(), vec![0.0; 12]]);
+        assert_eq!(Image::from_matrix_row(&m, 0, (2, 3, 2)).unwrap(), image);
+        assert!(Image::from_matrix_row(&m, 2, (2, 3, 2)).is_err());
+
+        let pixels = vec![0.5; 28 * 28];
+        let entry = MnistEntry {
+            image: &pixels,
+            label: &[1.0],
+        };
+        assert_eq!(Image::from_mnist(&entry).unwrap().shape(), (1, 28, 28));
+
+        let mut ragged = image.0.clone();
+        ragged[1][1].push(0.0);
+        assert!(Image::from_nested(ragged).is_err());
+        assert!(Image::from_nested(vec![vec![vec![0.0]], vec![]]).is_err());
+        assert!(Image::from_nested(image.0.clone()).is_ok());
+    }
+
+    #[test]
+    fn accessors_work() {
+        let empty = Image(Vec::new());
+        assert_eq!(empty.shape(), (0, 0, 0));
+        assert!(empty.validate().is_ok());
+        assert_eq!(Image(vec![Vec::new()]).shape(), (1, 0, 0));
+
+        let mut image = Image::new(3, 2, 4);
+        assert_eq!(image.get(2, 1, 3), Some(0.0));
+        assert_eq!(image.get(3, 0, 0), None);
+        assert_eq!(image.get(0, 0, 4), None);
+        *image.get_mut(1, 1, 2).unwrap() = 0.25;
+        assert_eq!(image.0[1][1][2], 0.25);
+        for v in image.channel_mut(2).unwrap() {
+            *v = 1.0;
+        }
+        assert_eq!(image.channel(2).unwrap().sum::<f64>(), 8.0);
+        assert_eq!(image.channel(1).unwrap().sum::<f64>(), 0.25);
+        assert!(image.channel(3).is_none());
+        assert!(image.channel_mut(3).is_none());
+    }
+
+    #[test]
+    fn save_and_load_work() {
+        let dir = TempDir::new("dlfs-image");
+
+        let pixels = (0..3 * 4 * 5).map(|i| (i * 4) as u8).collect::<Vec<_>>();
+        let image = Image::from_u8(3, 4, 5, &pixels);
+        for name in &["a.png", "a.PPM", "a.pnm"] {
+            image.save(dir.join(name)).unwrap();
+            let loaded = Image::load(dir.join(name)).unwrap();
+            assert_eq!(loaded.to_u8(), pixels);
+        }
+
+        assert!(image.save(dir.join("a.jpg")).is_err());
+        assert!(!dir.join("a.jpg").exists());
+        assert!(Image::from_u8(2, 1, 1, &[0, 0])
+            .save(dir.join("b.pgm"))
+            .is_err());
+        assert!(!dir.join("b.pgm").exists());
+        assert!(Image::new(1, 0, 0).save(dir.join("b.png")).is_err());
+        assert!(!dir.join("b.png").exists());
+
+        // Headers that claim huge images
+        assert!(Image::read_pnm(&b"P5 100000 100000 255 "[..]).is_err());
+        assert!(Image::read_pnm(&b"P2 18446744073709551615 2 255 "[..]).is_err());
+    }
+}
@@ -0,0 +1,602 @@
+// PNG: https://www.w3.org/TR/png/
+//
+// Only 8-bit, non-interlaced images are supported.
+// Images are written without compression (i.e., as "stored" deflate blocks).
+use std::io::{self, Read, Write};
+
+use super::{invalid_data, value_count, Image};
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+impl Image {
+    // Gray, gray + alpha, RGB, RGBA and palette images are read as 1, 2, 3, 4 and 3 channels
+    pub fn read_png<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        if !bytes.starts_with(&SIGNATURE) {
+            return invalid_data("Not a PNG file".to_owned());
+        }
+
+        let mut header = None;
+        let mut palette = Vec::new();
+        let mut compressed = Vec::new();
+        let mut rest = &bytes[SIGNATURE.len()..];
+        loop {
+            if rest.len() < 12 {
+                return invalid_data("Truncated PNG chunk".to_owned());
+            }
+            let len = be_u32(&rest[0..4]) as usize;
+            if rest.len() < 12 + len {
+                return invalid_data("Truncated PNG chunk".to_owned());
+            }
+            let kind = &rest[4..8];
+            let data = &rest[8..8 + len];
+            if be_u32(&rest[8 + len..12 + len]) != crc32(&rest[4..8 + len]) {
+                return invalid_data(format!(
+                    "CRC mismatch in {:?} chunk",
+                    String::from_utf8_lossy(kind)
+                ));
+            }
+            match kind {
+                b"IHDR" => header = Some(Header::parse(data)?),
+                b"PLTE" => palette = data.to_vec(),
+                b"IDAT" => compressed.extend_from_slice(data),
+                b"IEND" => break,
+                _ => {}
+            }
+            rest = &rest[12 + len..];
+        }
+
+        let header = match header {
+            Some(header) => header,
+            None => return invalid_data("IHDR chunk is missing".to_owned()),
+        };
+        // Each scanline starts with a filter type byte
+        let raw_size = (header.width * header.channels + 1) * header.height;
+        let raw = zlib_decompress(&compressed, raw_size)?;
+        let pixels = unfilter(&raw, &header)?;
+        let (channels, pixels) = if header.color_type == 3 {
+            let mut rgb = Vec::with_capacity(pixels.len() * 3);
+            for &i in &pixels {
+                match palette.get(i as usize * 3..i as usize * 3 + 3) {
+                    Some(color) => rgb.extend_from_slice(color),
+                    None => return invalid_data(format!("Palette index out of range: {}", i)),
+                }
+            }
+            (3, rgb)
+        } else {
+            (header.channels, pixels)
+        };
+        let values = pixels
+            .into_iter()
+            .map(|v| f64::from(v) / 255.0)
+            .collect::<Vec<_>>();
+        Ok(Image::from_interleaved(
+            channels,
+            header.height,
+            header.width,
+            &values,
+        ))
+    }
+
+    // 1, 2, 3 and 4 channels are written as gray, gray + alpha, RGB and RGBA images
+    pub fn write_png<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let color_type = self.png_color_type()?;
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(self.width() as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(self.height() as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[8, color_type, 0, 0, 0]);
+
+        // Every scanline starts with the filter type (0: None)
+        let pixels = self.to_interleaved_u8();
+        let mut raw = Vec::with_capacity(pixels.len() + self.height());
+        for row in pixels.chunks(self.width() * self.channels()) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+
+        writer.write_all(&SIGNATURE)?;
+        write_chunk(&mut writer, b"IHDR", &ihdr)?;
+        write_chunk(&mut writer, b"IDAT", &zlib_store(&raw))?;
+        write_chunk(&mut writer, b"IEND", &[])
+    }
+}
+
+impl Image {
+    // Fails if the image cannot be written as a PNG
+    pub(super) fn png_color_type(&self) -> io::Result<u8> {
+        let color_type = match self.channels() {
+            1 => 0,
+            2 => 4,
+            3 => 2,
+            4 => 6,
+            n => {
+                let message = format!("PNG needs 1 to 4 channels, but got {}", n);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            }
+        };
+        if self.height() == 0 || self.width() == 0 {
+            let message = "PNG cannot represent empty images".to_owned();
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        Ok(color_type)
+    }
+}
+
+#[derive(Debug)]
+struct Header {
+    width: usize,
+    height: usize,
+    color_type: u8,
+    channels: usize,
+}
+impl Header {
+    fn parse(data: &[u8]) -> io::Result<Self> {
+        if data.len() != 13 {
+            return invalid_data("Invalid IHDR chunk".to_owned());
+        }
+        let (bit_depth, color_type, interlace) = (data[8], data[9], data[12]);
+        let channels = match color_type {
+            0 | 3 => 1,
+            4 => 2,
+            2 => 3,
+            6 => 4,
+            _ => return invalid_data(format!("Unknown PNG color type: {}", color_type)),
+        };
+        if bit_depth != 8 || interlace != 0 {
+            return invalid_data(format!(
+                "Unsupported PNG: bit depth {}, interlace method {}",
+                bit_depth, interlace
+            ));
+        }
+        let width = be_u32(&data[0..4]) as usize;
+        let height = be_u32(&data[4..8]) as usize;
+
+        // Palette images are expanded to RGB
+        value_count(
+            channels.max(if color_type == 3 { 3 } else { 0 }),
+            height,
+            width,
+        )?;
+        Ok(Header {
+            width,
+            height,
+            color_type,
+            channels,
+        })
+    }
+}
+
+fn unfilter(raw: &[u8], header: &Header) -> io::Result<Vec<u8>> {
+    let bpp = header.channels;
+    let stride = header.width * bpp;
+    if raw.len() != (stride + 1) * header.height {
+        return invalid_data(format!(
+            "Unexpected size of PNG image data: {} bytes",
+            raw.len()
+        ));
+    }
+
+    let mut pixels = vec![0u8; stride * header.height];
+    for (y, line) in raw.chunks(stride + 1).enumerate() {
+        let (done, current) = pixels.split_at_mut(y * stride);
+        let previous = if y == 0 {
+            None
+        } else {
+            Some(&done[(y - 1) * stride..])
+        };
+        let current = &mut current[..stride];
+        for x in 0..stride {
+            let a = if x >= bpp { current[x - bpp] } else { 0 };
+            let b = previous.map_or(0, |p| p[x]);
+            let c = if x >= bpp {
+                previous.map_or(0, |p| p[x - bpp])
+            } else {
+                0
+            };
+            let predictor = match line[0] {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((u16::from(a) + u16::from(b)) / 2) as u8,
+                4 => paeth(a, b, c),
+                f => return invalid_data(format!("Unknown PNG filter type: {}", f)),
+            };
+            current[x] = line[x + 1].wrapping_add(predictor);
+        }
+    }
+    Ok(pixels)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = i16::from(a) + i16::from(b) - i16::from(c);
+    let (pa, pb, pc) = (
+        (p - i16::from(a)).abs(),
+        (p - i16::from(b)).abs(),
+        (p - i16::from(c)).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn write_chunk<W: Write>(mut writer: W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let mut crc_data = kind.to_vec();
+    crc_data.extend_from_slice(data);
+    writer.write_all(&crc32(&crc_data).to_be_bytes())
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &v in bytes {
+        a = (a + u32::from(v)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+// A zlib stream made of uncompressed blocks
+fn zlib_store(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+// Fails if the output exceeds `limit` bytes (e.g., a crafted stream that expands to gigabytes)
+fn zlib_decompress(data: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    if data.len() < 6 || data[0] & 0x0F != 8 || data[1] & 0x20 != 0 {
+        return invalid_data("Unsupported zlib stream".to_owned());
+    }
+    if (u16::from(data[0]) << 8 | u16::from(data[1])) % 31 != 0 {
+        return invalid_data("Invalid zlib header check bits".to_owned());
+    }
+    let (out, end) = inflate(&data[2..], limit)?;
+    // The checksum follows the deflate stream (which may be followed by garbage)
+    match data.get(2 + end..2 + end + 4) {
+        Some(checksum) if be_u32(checksum) == adler32(&out) => Ok(out),
+        Some(_) => invalid_data("Adler-32 mismatch in zlib stream".to_owned()),
+        None => invalid_data("Adler-32 is missing in zlib stream".to_owned()),
+    }
+}
+
+// DEFLATE: https://www.rfc-editor.org/rfc/rfc1951
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+impl<'a> BitReader<'a> {
+    // Reads `n` bits, least significant bit first
+    fn bits(&mut self, n: u8) -> io::Result<u32> {
+        let mut v = 0;
+        for i in 0..n {
+            let byte = match self.bytes.get(self.position / 8) {
+                Some(&b) => b,
+                None => return invalid_data("Unexpected end of deflate stream".to_owned()),
+            };
+            v |= u32::from((byte >> (self.position % 8)) & 1) << i;
+            self.position += 1;
+        }
+        Ok(v)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.position = self.position.div_ceil(8) * 8;
+    }
+}
+
+// Canonical Huffman codes
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for &l in lengths {
+            counts[l as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols = (0..lengths.len() as u16)
+            .filter(|&s| lengths[s as usize] != 0)
+            .collect::<Vec<_>>();
+        symbols.sort_by_key(|&s| lengths[s as usize]);
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = i32::from(self.counts[len]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        invalid_data("Invalid Huffman code".to_owned())
+    }
+}
+
+// Returns the output and the number of bytes consumed from `data`
+fn inflate(data: &[u8], limit: usize) -> io::Result<(Vec<u8>, usize)> {
+    let mut reader = BitReader {
+        bytes: data,
+        position: 0,
+    };
+    let mut out = Vec::new();
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align_to_byte();
+                let len = reader.bits(16)? as usize;
+                let nlen = reader.bits(16)? as usize;
+                let start = reader.position / 8;
+                if len != !nlen & 0xFFFF || data.len() < start + len {
+                    return invalid_data("Invalid stored deflate block".to_owned());
+                }
+                out.extend_from_slice(&data[start..start + len]);
+                reader.position += len * 8;
+                check_limit(&out, limit)?;
+            }
+            1 => {
+                let mut lengths = [0u8; 288];
+                for (i, l) in lengths.iter_mut().enumerate() {
+                    *l = match i {
+                        0..=143 => 8,
+                        144..=255 => 9,
+                        256..=279 => 7,
+                        _ => 8,
+                    };
+                }
+                let literals = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_block(&mut reader, &literals, &distances, &mut out, limit)?;
+            }
+            2 => {
+                let (literals, distances) = read_dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &literals, &distances, &mut out, limit)?;
+            }
+            _ => return invalid_data("Invalid deflate block type".to_owned()),
+        }
+        if last {
+            reader.align_to_byte();
+            return Ok((out, reader.position / 8));
+        }
+    }
+}
+
+fn read_dynamic_codes(reader: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &i in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[i] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => match lengths.last() {
+                Some(&previous) => (previous, 3 + reader.bits(2)?),
+                None => return invalid_data("Invalid code length repeat".to_owned()),
+            },
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend((0..repeat).map(|_| value));
+    }
+    if lengths.len() != literal_count + distance_count {
+        return invalid_data("Too many code lengths".to_owned());
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    literals: &Huffman,
+    distances: &Huffman,
+    out: &mut Vec<u8>,
+    limit: usize,
+) -> io::Result<()> {
+    loop {
+        check_limit(out, limit)?;
+        let symbol = literals.decode(reader)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let i = symbol - 257;
+        if i >= LENGTH_BASE.len() {
+            return invalid_data(format!("Invalid length symbol: {}", symbol));
+        }
+        let len = LENGTH_BASE[i] as usize + reader.bits(LENGTH_EXTRA[i])? as usize;
+        let j = distances.decode(reader)? as usize;
+        if j >= DISTANCE_BASE.len() {
+            return invalid_data(format!("Invalid distance symbol: {}", j));
+        }
+        let distance = DISTANCE_BASE[j] as usize + reader.bits(DISTANCE_EXTRA[j])? as usize;
+        if distance > out.len() {
+            return invalid_data("Too far back reference".to_owned());
+        }
+        let start = out.len() - distance;
+        for k in 0..len {
+            let v = out[start + k];
+            out.push(v);
+        }
+    }
+}
+
+fn check_limit(out: &[u8], limit: usize) -> io::Result<()> {
+    if out.len() > limit {
+        return invalid_data(format!("Deflate output exceeds {} bytes", limit));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inflate_works() {
+        // zlib.compress(b"abcabcabcabcabcabc", 9) (fixed Huffman codes)
+        let fixed = [120, 218, 75, 76, 74, 78, 68, 69, 0, 65, 124, 6, 229];
+        assert_eq!(zlib_decompress(&fixed, 100).unwrap(), b"abcabcabcabcabcabc");
+        assert!(zlib_decompress(&fixed, 17).is_err());
+
+        // zlib.compress(b"abeccebbcfddfccdgeegddeaffaeef", 9) (dynamic Huffman codes)
+        let dynamic = [
+            120, 218, 5, 193, 7, 1, 0, 0, 8, 195, 48, 173, 59, 29, 254, 29, 144, 200, 36, 216, 89,
+            187, 164, 7, 215, 162, 77, 176, 7, 181, 79, 11, 188,
+        ];
+        assert_eq!(
+            zlib_decompress(&dynamic, 100).unwrap(),
+            &b"abeccebbcfddfccdgeegddeaffaeef"[..]
+        );
+
+        let data = (0..70000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        assert_eq!(
+            zlib_decompress(&zlib_store(&data), data.len()).unwrap(),
+            data
+        );
+        assert_eq!(zlib_decompress(&zlib_store(&[]), 0).unwrap(), []);
+
+        // Trailing bytes after the checksum are ignored, but a broken header or checksum is not
+        let mut trailing = fixed.to_vec();
+        trailing.extend_from_slice(&[0, 0]);
+        assert!(zlib_decompress(&trailing, 100).is_ok());
+        let mut broken = fixed;
+        broken[1] ^= 1;
+        assert!(zlib_decompress(&broken, 100).is_err());
+        let mut broken = fixed;
+        broken[12] ^= 1;
+        assert!(zlib_decompress(&broken, 100).is_err());
+        assert!(zlib_decompress(&fixed[..12], 100).is_err());
+    }
+
+    #[test]
+    fn read_png_works() {
+        // A 3x2 RGB image whose rows use the Sub and Paeth filters
+        let png = [
+            137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 3, 0, 0, 0, 2,
+            8, 2, 0, 0, 0, 18, 22, 241, 77, 0, 0, 0, 24, 73, 68, 65, 84, 120, 218, 99, 228, 18,
+            145, 131, 0, 22, 86, 86, 214, 5, 70, 223, 204, 231, 168, 1, 0, 24, 255, 3, 198, 91,
+            250, 119, 119, 0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130,
+        ];
+        let image = Image::read_png(&png[..]).unwrap();
+        assert_eq!((image.channels(), image.height(), image.width()), (3, 2, 3));
+        assert_eq!(
+            image.to_u8(),
+            [
+                10, 40, 70, 15, 200, 255, // red
+                20, 50, 80, 25, 100, 0, // green
+                30, 60, 90, 35, 50, 128, // blue
+            ]
+        );
+
+        let mut broken = png;
+        broken[20] ^= 1;
+        assert!(Image::read_png(&broken[..]).is_err());
+        // A valid IHDR chunk that claims a 65536 x 65536 image
+        let mut huge = png;
+        huge[16..24].copy_from_slice(&[0, 1, 0, 0, 0, 1, 0, 0]);
+        let crc = crc32(&huge[12..29]).to_be_bytes();
+        huge[29..33].copy_from_slice(&crc);
+        let e = Image::read_png(&huge[..]).unwrap_err();
+        assert!(e.to_string().contains("Too large image"), "{}", e);
+
+        // A zero width must not let a huge height through
+        let mut narrow = png;
+        narrow[16..24].copy_from_slice(&[0, 0, 0, 0, 0xEE, 0x6B, 0x28, 0x00]);
+        let crc = crc32(&narrow[12..29]).to_be_bytes();
+        narrow[29..33].copy_from_slice(&crc);
+        let e = Image::read_png(&narrow[..]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(e.to_string().contains("Empty image"), "{}", e);
+    }
+
+    #[test]
+    fn write_png_works() {
+        for &channels in &[1, 2, 3, 4] {
+            let pixels = (0..channels * 5 * 4)
+                .map(|i| (i * 3) as u8)
+                .collect::<Vec<_>>();
+            let image = Image::from_u8(channels, 5, 4, &pixels);
+            let mut buf = Vec::new();
+            image.write_png(&mut buf).unwrap();
+            assert_eq!(Image::read_png(&buf[..]).unwrap().to_u8(), pixels);
+        }
+        assert!(Image::new(1, 0, 3).write_png(Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+// Netpbm formats: PGM (P2, P5) for grayscale and PPM (P3, P6) for RGB images
+use std::io::{self, Read, Write};
+
+use super::{invalid_data, value_count, Image};
+
+impl Image {
+    pub fn read_pnm<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut header = Header {
+            bytes: &bytes,
+            position: 0,
+        };
+
+        let magic = header.token()?;
+        let (channels, binary) = match magic {
+            "P2" => (1, false),
+            "P3" => (3, false),
+            "P5" => (1, true),
+            "P6" => (3, true),
+            _ => return invalid_data(format!("Unsupported PNM magic number: {:?}", magic)),
+        };
+        let width = header.number()?;
+        let height = header.number()?;
+        let max = header.number()?;
+        if max == 0 || max > 65535 {
+            return invalid_data(format!("Invalid PNM max value: {}", max));
+        }
+
+        let count = value_count(channels, height, width)?;
+        let values = if binary {
+            // A single whitespace separates the header and the pixels
+            let data = &bytes[(header.position + 1).min(bytes.len())..];
+            let size = if max < 256 { 1 } else { 2 };
+            if data.len() < count * size {
+                return invalid_data(format!(
+                    "Too short PNM data: {} bytes (expected {})",
+                    data.len(),
+                    count * size
+                ));
+            }
+            data.chunks(size)
+                .take(count)
+                .map(|v| v.iter().fold(0, |acc, &b| (acc << 8) | usize::from(b)))
+                .collect::<Vec<_>>()
+        } else {
+            (0..count)
+                .map(|_| header.number())
+                .collect::<io::Result<Vec<_>>>()?
+        };
+        if let Some(v) = values.iter().find(|&&v| v > max) {
+            return invalid_data(format!("PNM value {} exceeds the max value {}", v, max));
+        }
+
+        let values = values
+            .into_iter()
+            .map(|v| v as f64 / max as f64)
+            .collect::<Vec<_>>();
+        Ok(Image::from_interleaved(channels, height, width, &values))
+    }
+
+    // Writes 1-channel images as PGM (P5) and 3-channel images as PPM (P6)
+    pub fn write_pnm<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let magic = self.pnm_magic()?;
+        write!(
+            writer,
+            "{}\n{} {}\n255\n",
+            magic,
+            self.width(),
+            self.height()
+        )?;
+        writer.write_all(&self.to_interleaved_u8())
+    }
+}
+
+impl Image {
+    // Fails if the image cannot be written as a PGM or PPM
+    pub(super) fn pnm_magic(&self) -> io::Result<&'static str> {
+        let magic = match self.channels() {
+            1 => "P5",
+            3 => "P6",
+            n => {
+                let message = format!("PNM needs 1 or 3 channels, but got {}", n);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            }
+        };
+        if self.height() == 0 || self.width() == 0 {
+            let message = "PNM cannot represent empty images".to_owned();
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+        }
+        Ok(magic)
+    }
+}
+
+struct Header<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+impl<'a> Header<'a> {
+    // Skips whitespaces and comments, and returns the next token
+    fn token(&mut self) -> io::Result<&'a str> {
+        loop {
+            match self.bytes.get(self.position) {
+                Some(b'#') => {
+                    while self.bytes.get(self.position).is_some_and(|&b| b != b'\n') {
+                        self.position += 1;
+                    }
+                }
+                Some(b) if b.is_ascii_whitespace() => self.position += 1,
+                Some(_) => break,
+                None => return invalid_data("Unexpected end of PNM data".to_owned()),
+            }
+        }
+        let start = self.position;
+        while self
+            .bytes
+            .get(self.position)
+            .is_some_and(|b| !b.is_ascii_whitespace() && *b != b'#')
+        {
+            self.position += 1;
+        }
+        Ok(std::str::from_utf8(&self.bytes[start..self.position]).unwrap_or(""))
+    }
+
+    fn number(&mut self) -> io::Result<usize> {
+        let token = self.token()?;
+        token
+            .parse()
+            .or_else(|_| invalid_data(format!("Invalid number in PNM data: {:?}", token)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pgm_works() {
+        let image = Image::from_u8(1, 2, 3, &[0, 51, 102, 153, 204, 255]);
+        let mut buf = Vec::new();
+        image.write_pnm(&mut buf).unwrap();
+        assert!(buf.starts_with(b"P5\n3 2\n255\n"));
+
+        let loaded = Image::read_pnm(&buf[..]).unwrap();
+        assert_eq!(loaded.0, image.0);
+        assert_eq!(loaded.to_u8(), [0, 51, 102, 153, 204, 255]);
+    }
+
+    #[test]
+    fn ppm_works() {
+        let image = Image::from_u8(3, 1, 2, &[255, 0, 10, 20, 30, 40]);
+        let mut buf = Vec::new();
+        image.write_pnm(&mut buf).unwrap();
+
+        // Pixels are interleaved in files
+        assert_eq!(buf[buf.len() - 6..], [255, 10, 30, 0, 20, 40]);
+        assert_eq!(Image::read_pnm(&buf[..]).unwrap().0, image.0);
+
+        let rgba = Image(vec![vec![vec![0.0]]; 4]);
+        assert!(rgba.write_pnm(&mut Vec::new()).is_err());
+        assert!(Image::new(3, 2, 0).write_pnm(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn ascii_and_comments_work() {
+        let data = b"P2\n# a comment\n2 1 # another\n10\n0 10\n";
+        let image = Image::read_pnm(&data[..]).unwrap();
+        assert_eq!(image.0, [[[0.0, 1.0]]]);
+
+        let data = b"P5 1 1 65535\n\x80\x00";
+        let image = Image::read_pnm(&data[..]).unwrap();
+        assert_eq!(image.0[0][0][0], 32768.0 / 65535.0);
+
+        assert!(Image::read_pnm(&b"P5 2 2 255\n\x00"[..]).is_err());
+        assert!(Image::read_pnm(&b"P2 1 1 10\n11"[..]).is_err());
+        assert!(Image::read_pnm(&b"P7 1 1 10\n"[..]).is_err());
+
+        // A zero width must not let a huge height through
+        let e = Image::read_pnm(&b"P5 0 4000000000 255\n"[..]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(Image::read_pnm(&b"P5 1 4000000000 255\n"[..]).is_err());
+        assert!(Image::read_pnm(&b"P5 0 0 255\n"[..]).is_err());
+    }
+}
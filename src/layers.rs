@@ -35,6 +35,11 @@ impl ReluLayer {
         Matrix::from(dx)
     }
 }
+impl Default for ReluLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 #[derive(Debug)]
 struct ReluLayerInner {
@@ -88,7 +93,7 @@ impl SigmoidLayer {
         self.out.clear();
         xs.map(move |x| {
             let y = sigmoid(x);
-            self.out.push(x);
+            self.out.push(y);
             y
         })
     }
@@ -103,6 +108,11 @@ impl SigmoidLayer {
             .map(|(y, dout)| dout * (1.0 - y) * y)
     }
 }
+impl Default for SigmoidLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 #[derive(Debug)]
 pub struct AffineLayer {
@@ -165,7 +175,170 @@ impl SoftmaxWithLossLayer {
         let batch_size = self.t.rows();
 
         // NOTE: `AffineLayer`でバッチサイズ分の合算が行われるので、ここであらかじめ`batch_size`で割って単位を合わせておく
-        let dx = self.y.clone().sub(&self.t) / (batch_size as f64);
-        dx
+        self.y.clone().sub(&self.t) / (batch_size as f64)
+    }
+}
+impl Default for SoftmaxWithLossLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gradient::{gradient_check, Differentiable};
+
+    // Reduces a layer output to a scalar so that `dout` becomes its gradient
+    fn weighted_sum(y: Matrix, dout: &Matrix) -> f64 {
+        (y * dout.clone()).into_vec().into_iter().flatten().sum()
+    }
+
+    fn assert_gradients<M: Differentiable>(model: &mut M, t: &Matrix) {
+        for e in gradient_check(model, &Matrix::new(0, 0), t) {
+            assert!(e.max_absolute_error < 1e-7, "{:?}", e);
+        }
+    }
+
+    struct ReluCheck {
+        layer: ReluLayer,
+        x: Matrix,
+    }
+    impl Differentiable for ReluCheck {
+        fn parameter_names(&self) -> Vec<&'static str> {
+            vec!["x"]
+        }
+
+        fn parameter_mut(&mut self, _index: usize) -> &mut Matrix {
+            &mut self.x
+        }
+
+        fn loss(&mut self, _x: &Matrix, dout: &Matrix) -> f64 {
+            weighted_sum(self.layer.forward(self.x.clone()), dout)
+        }
+
+        fn gradients(&mut self, _x: &Matrix, dout: &Matrix) -> Vec<Matrix> {
+            self.layer.forward(self.x.clone());
+            vec![self.layer.backward(dout.clone())]
+        }
+    }
+
+    struct SigmoidCheck {
+        layer: SigmoidLayer,
+        x: Matrix,
+    }
+    impl Differentiable for SigmoidCheck {
+        fn parameter_names(&self) -> Vec<&'static str> {
+            vec!["x"]
+        }
+
+        fn parameter_mut(&mut self, _index: usize) -> &mut Matrix {
+            &mut self.x
+        }
+
+        fn loss(&mut self, _x: &Matrix, dout: &Matrix) -> f64 {
+            let y = self.layer.forward(self.x.row(0).cloned()).collect();
+            weighted_sum(Matrix::from(vec![y]), dout)
+        }
+
+        fn gradients(&mut self, _x: &Matrix, dout: &Matrix) -> Vec<Matrix> {
+            self.layer.forward(self.x.row(0).cloned()).for_each(|_| {});
+            let dx = self.layer.backword(dout.row(0).cloned()).collect();
+            vec![Matrix::from(vec![dx])]
+        }
+    }
+
+    struct AffineCheck {
+        layer: AffineLayer,
+        x: Matrix,
+    }
+    impl Differentiable for AffineCheck {
+        fn parameter_names(&self) -> Vec<&'static str> {
+            vec!["x", "w", "b"]
+        }
+
+        fn parameter_mut(&mut self, index: usize) -> &mut Matrix {
+            match index {
+                0 => &mut self.x,
+                1 => &mut self.layer.w,
+                _ => &mut self.layer.b,
+            }
+        }
+
+        fn loss(&mut self, _x: &Matrix, dout: &Matrix) -> f64 {
+            weighted_sum(self.layer.forward(self.x.clone()), dout)
+        }
+
+        fn gradients(&mut self, _x: &Matrix, dout: &Matrix) -> Vec<Matrix> {
+            self.layer.forward(self.x.clone());
+            let dx = self.layer.backward(dout.clone());
+            vec![dx, self.layer.dw.clone(), self.layer.db.clone()]
+        }
+    }
+
+    struct SoftmaxWithLossCheck {
+        layer: SoftmaxWithLossLayer,
+        x: Matrix,
+    }
+    impl Differentiable for SoftmaxWithLossCheck {
+        fn parameter_names(&self) -> Vec<&'static str> {
+            vec!["x"]
+        }
+
+        fn parameter_mut(&mut self, _index: usize) -> &mut Matrix {
+            &mut self.x
+        }
+
+        fn loss(&mut self, _x: &Matrix, t: &Matrix) -> f64 {
+            let loss = self.layer.forward(self.x.clone(), t.clone());
+            loss.iter().sum::<f64>() / (loss.len() as f64)
+        }
+
+        fn gradients(&mut self, _x: &Matrix, t: &Matrix) -> Vec<Matrix> {
+            self.layer.forward(self.x.clone(), t.clone());
+            vec![self.layer.backword()]
+        }
+    }
+
+    #[test]
+    fn relu_layer_gradient_works() {
+        let mut check = ReluCheck {
+            layer: ReluLayer::new(),
+            x: Matrix::from(vec![vec![1.0, -0.5, 0.3], vec![-2.0, 0.7, -0.1]]),
+        };
+        let dout = Matrix::from(vec![vec![0.2, -0.3, 0.5], vec![0.1, 0.4, -0.6]]);
+        assert_gradients(&mut check, &dout);
+    }
+
+    #[test]
+    fn sigmoid_layer_gradient_works() {
+        let mut check = SigmoidCheck {
+            layer: SigmoidLayer::new(),
+            x: Matrix::from(vec![vec![1.0, -0.5, 0.3]]),
+        };
+        let dout = Matrix::from(vec![vec![0.2, -0.3, 0.5]]);
+        assert_gradients(&mut check, &dout);
+    }
+
+    #[test]
+    fn affine_layer_gradient_works() {
+        let w = Matrix::from(vec![vec![0.1, 0.4], vec![0.2, -0.5], vec![0.3, 0.6]]);
+        let b = Matrix::from(vec![vec![0.1, -0.2]]);
+        let mut check = AffineCheck {
+            layer: AffineLayer::new(w, b),
+            x: Matrix::from(vec![vec![1.0, -0.5, 0.3], vec![-2.0, 0.7, -0.1]]),
+        };
+        let dout = Matrix::from(vec![vec![0.2, -0.3], vec![0.1, 0.4]]);
+        assert_gradients(&mut check, &dout);
+    }
+
+    #[test]
+    fn softmax_with_loss_layer_gradient_works() {
+        let mut check = SoftmaxWithLossCheck {
+            layer: SoftmaxWithLossLayer::new(),
+            x: Matrix::from(vec![vec![1.0, -0.5, 0.3], vec![-2.0, 0.7, -0.1]]),
+        };
+        let t = Matrix::from(vec![vec![0.0, 0.0, 1.0], vec![0.0, 1.0, 0.0]]);
+        assert_gradients(&mut check, &t);
     }
 }
@@ -6,7 +6,14 @@ pub mod ch05;
 pub mod data;
 pub mod functions;
 pub mod gradient;
+pub mod idx;
 pub mod image;
 pub mod layers;
 pub mod matrix;
 pub mod optimize;
+pub mod preprocess;
+pub mod random;
+pub mod scheduler;
+
+#[cfg(test)]
+mod test_util;
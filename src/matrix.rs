@@ -1,10 +1,11 @@
 use rand::distributions::StandardNormal;
-use rand::rngs::StdRng;
-use rand::{FromEntropy, Rng};
+use rand::Rng;
+use std::io::{self, Read, Write};
 use std::iter::Sum;
 use std::ops::{Add, AddAssign, Div, Mul, Sub, SubAssign};
 
 use image::Image;
+use random;
 
 // FIXME: optimize inner representation
 //        (e.g., `{ inner: Vec<T>, rows: usize, cols: usize }`)
@@ -20,11 +21,14 @@ where
 }
 impl Matrix<f64> {
     pub fn with_randn(rows: usize, columns: usize) -> Self {
+        random::with_rng(|rng| Self::with_randn_rng(rows, columns, rng))
+    }
+
+    pub fn with_randn_rng<R: Rng>(rows: usize, columns: usize, rng: &mut R) -> Self {
         let mut m = Self::new(rows, columns);
-        let mut rng = StdRng::from_entropy();
         for row in m.0.iter_mut() {
             for cell in row.iter_mut() {
-                *cell = rng.sample(&StandardNormal);
+                *cell = rng.sample(StandardNormal);
             }
         }
         m
@@ -43,19 +47,51 @@ impl Matrix<f64> {
     where
         I: Iterator<Item = Image>,
     {
+        Self::try_from_images(images, filter_h, filter_w, stride, pad).expect("Invalid image")
+    }
+
+    // Same as `from_images`, but fails on ragged images, mixed channel counts, and `stride == 0`
+    // instead of panicking
+    pub fn try_from_images<I>(
+        images: I,
+        filter_h: usize,
+        filter_w: usize,
+        stride: usize,
+        pad: usize,
+    ) -> io::Result<Self>
+    where
+        I: Iterator<Item = Image>,
+    {
+        if stride == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The stride must be positive",
+            ));
+        }
         let filter_h = filter_h as isize;
         let filter_w = filter_w as isize;
         let pad = pad as isize;
 
+        let mut channels = None;
         let mut rows = Vec::new();
         for image in images {
+            image.validate()?;
+            let expected = *channels.get_or_insert(image.channels());
+            if image.channels() != expected {
+                let message = format!(
+                    "An image has {} channels, but the first one has {}",
+                    image.channels(),
+                    expected
+                );
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            }
             let height = image.height() as isize;
             let width = image.width() as isize;
 
-            let y_start = -(pad as isize);
-            let y_end = (height + pad) as isize;
-            let x_start = -(pad as isize);
-            let x_end = (width + pad) as isize;
+            let y_start = -pad;
+            let y_end = height + pad;
+            let x_start = -pad;
+            let x_end = width + pad;
             for y in y_start..(y_end - filter_h) + 1 {
                 if (y - y_start) % (stride as isize) != 0 {
                     continue;
@@ -82,7 +118,7 @@ impl Matrix<f64> {
                 }
             }
         }
-        Matrix::from(rows)
+        Ok(Matrix::from(rows))
     }
 
     pub fn column_sum(&self) -> Matrix {
@@ -154,6 +190,7 @@ where
         out
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn add(mut self, other: &Self) -> Self {
         assert_eq!(
             self.rows(),
@@ -194,6 +231,7 @@ where
         self
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn sub(mut self, other: &Self) -> Self {
         assert_eq!(
             self.rows(),
@@ -222,7 +260,7 @@ where
     }
 
     pub fn columns(&self) -> usize {
-        self.0.get(0).map_or(0, |x| x.len())
+        self.0.first().map_or(0, |x| x.len())
     }
 
     pub fn shape(&self) -> (usize, usize) {
@@ -266,6 +304,26 @@ where
         self
     }
 
+    pub fn zip_map<F>(mut self, other: &Self, f: F) -> Self
+    where
+        F: Fn(&T, &T) -> T,
+    {
+        assert_eq!(
+            self.shape(),
+            other.shape(),
+            "self={:?}, rhs={:?}",
+            self.shape(),
+            other.shape()
+        );
+        for y in 0..self.rows() {
+            for x in 0..self.columns() {
+                let t = f(&self.0[y][x], &other.0[y][x]);
+                self.0[y][x] = t;
+            }
+        }
+        self
+    }
+
     pub fn map_row<F>(self, f: F) -> Self
     where
         F: Fn(&[T]) -> Vec<T>,
@@ -274,7 +332,53 @@ where
         Matrix(m)
     }
 }
+// The limits of `Matrix::read_from` (i.e., 2 GiB of cells at most)
+const MAX_ROWS: u64 = 1 << 24;
+const MAX_CELLS: u64 = 1 << 28;
+
 impl Matrix {
+    // Format: rows (u64), columns (u64) and then the cells in row-major order (f64), all little-endian
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&(self.rows() as u64).to_le_bytes())?;
+        writer.write_all(&(self.columns() as u64).to_le_bytes())?;
+        for row in &self.0 {
+            for cell in row {
+                writer.write_all(&cell.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    // Fails with `InvalidData` on implausible shapes (e.g., a corrupt checkpoint).
+    // Cells are allocated as they are read, so a truncated stream fails before allocating much.
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut buf = [0; 8];
+        reader.read_exact(&mut buf)?;
+        let rows = u64::from_le_bytes(buf);
+        reader.read_exact(&mut buf)?;
+        let columns = u64::from_le_bytes(buf);
+        let cells = rows.checked_mul(columns);
+        if rows > MAX_ROWS || cells.is_none_or(|n| n > MAX_CELLS) {
+            let message = format!("Too large matrix: {} x {}", rows, columns);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+
+        let mut m = Vec::new();
+        for _ in 0..rows {
+            let mut row = Vec::new();
+            for _ in 0..columns {
+                reader.read_exact(&mut buf)?;
+                row.push(f64::from_le_bytes(buf));
+            }
+            m.push(row);
+        }
+        Ok(Matrix(m))
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.0.iter().map(|row| row.iter().sum::<f64>()).sum()
+    }
+
     pub fn sqrt(&self) -> Matrix {
         let mut m = self.clone();
         for row in m.0.iter_mut() {
@@ -287,7 +391,7 @@ impl Matrix {
 }
 impl<T> From<Vec<Vec<T>>> for Matrix<T> {
     fn from(f: Vec<Vec<T>>) -> Self {
-        let columns = f.get(0).map_or(0, |x| x.len());
+        let columns = f.first().map_or(0, |x| x.len());
         for row in &f {
             // FIXME
             assert_eq!(row.len(), columns);
@@ -427,15 +531,41 @@ mod tests {
         assert_eq!(y, [0.3164209556565184, 0.6954092315109959]);
     }
 
+    #[test]
+    fn write_and_read_works() {
+        let m = Matrix::from(vec![vec![1.0, -2.5, 3.0], vec![0.0, 1e-9, -7.0]]);
+        let mut buf = Vec::new();
+        m.write_to(&mut buf).unwrap();
+        assert_eq!(buf.len(), 16 + 6 * 8);
+        assert_eq!(Matrix::read_from(&buf[..]).unwrap(), m);
+        assert!(Matrix::read_from(&buf[..buf.len() - 1]).is_err());
+
+        // Corrupt shapes fail without trying to allocate them
+        for &(rows, columns) in &[(u64::MAX, 2u64), (1 << 20, 1 << 20), (1 << 40, 0)] {
+            let mut corrupt = rows.to_le_bytes().to_vec();
+            corrupt.extend_from_slice(&columns.to_le_bytes());
+            let e = Matrix::read_from(&corrupt[..]).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
     #[test]
     fn im2col() {
-        use std::iter::{once, repeat};
+        use std::iter::{once, repeat_n};
 
         let image = Image(vec![vec![vec![0.0; 7]; 7]; 3]);
         let m = Matrix::from_images(once(image.clone()), 5, 5, 1, 0);
         assert_eq!(m.shape(), (9, 75));
 
-        let m = Matrix::from_images(repeat(image).take(10), 5, 5, 1, 0);
+        let m = Matrix::from_images(repeat_n(image, 10), 5, 5, 1, 0);
         assert_eq!(m.shape(), (90, 75));
+
+        let mut ragged = Image::new(3, 7, 7);
+        ragged.0[2][4].pop();
+        assert!(Matrix::try_from_images(once(ragged), 5, 5, 1, 0).is_err());
+
+        let mixed = vec![Image::new(1, 7, 7), Image::new(3, 7, 7)];
+        assert!(Matrix::try_from_images(mixed.into_iter(), 5, 5, 1, 0).is_err());
+        assert!(Matrix::try_from_images(once(Image::new(3, 7, 7)), 5, 5, 0, 0).is_err());
     }
 }
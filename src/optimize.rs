@@ -1,17 +1,134 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::mem;
+
 use matrix::Matrix;
 
 #[derive(Debug)]
 pub struct Pairs<'a> {
+    // Identifies the parameter across updates (e.g., "affine1.w")
+    pub name: &'a str,
     pub param: &'a mut Matrix,
     pub gradient: &'a Matrix,
 }
 
 pub trait Optimizer {
+    fn learning_rate(&self) -> f64;
+
+    fn set_learning_rate(&mut self, learning_rate: f64);
+
     fn update<'a, 'b, I>(&'a mut self, pairs: I)
     where
         I: Iterator<Item = Pairs<'b>>;
 }
 
+// Per-parameter optimizer state keyed by `Pairs::name`
+#[derive(Debug, Default)]
+struct States(HashMap<String, Matrix>);
+impl States {
+    fn get_mut(&mut self, pairs: &Pairs) -> &mut Matrix {
+        let (rows, columns) = pairs.param.shape();
+        self.entry(pairs, || Matrix::new(rows, columns))
+    }
+
+    fn entry<F>(&mut self, pairs: &Pairs, init: F) -> &mut Matrix
+    where
+        F: FnOnce() -> Matrix,
+    {
+        if !self.0.contains_key(pairs.name) {
+            self.0.insert(pairs.name.to_owned(), init());
+        }
+        let m = self.0.get_mut(pairs.name).expect("Never fails");
+        assert_eq!(m.shape(), pairs.param.shape(), "parameter={:?}", pairs.name);
+        m
+    }
+
+    fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut names = self.0.keys().collect::<Vec<_>>();
+        names.sort();
+        writer.write_all(&(names.len() as u64).to_le_bytes())?;
+        for name in names {
+            write_name(&mut writer, name)?;
+            self.0[name].write_to(&mut writer)?;
+        }
+        Ok(())
+    }
+
+    fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut states = HashMap::new();
+        for _ in 0..read_len(&mut reader, MAX_ENTRIES)? {
+            let name = read_name(&mut reader)?;
+            let m = Matrix::read_from(&mut reader)?;
+            states.insert(name, m);
+        }
+        Ok(States(states))
+    }
+}
+
+fn read_u64<R: Read>(mut reader: R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+// The limits of the lengths read from saved states (which may be corrupt)
+const MAX_ENTRIES: usize = 1 << 20;
+const MAX_NAME_LEN: usize = 1 << 16;
+
+fn read_len<R: Read>(reader: R, max: usize) -> io::Result<usize> {
+    let len = read_u64(reader)?;
+    if len > max as u64 {
+        let message = format!("Too large length: {} (max {})", len, max);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    Ok(len as usize)
+}
+
+fn write_name<W: Write>(mut writer: W, name: &str) -> io::Result<()> {
+    writer.write_all(&(name.len() as u64).to_le_bytes())?;
+    writer.write_all(name.as_bytes())
+}
+
+fn read_name<R: Read>(mut reader: R) -> io::Result<String> {
+    let mut buf = vec![0; read_len(&mut reader, MAX_NAME_LEN)?];
+    reader.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_iters<W: Write>(mut writer: W, iters: &HashMap<String, i32>) -> io::Result<()> {
+    let mut names = iters.keys().collect::<Vec<_>>();
+    names.sort();
+    writer.write_all(&(names.len() as u64).to_le_bytes())?;
+    for name in names {
+        write_name(&mut writer, name)?;
+        writer.write_all(&iters[name].to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn read_iters<R: Read>(mut reader: R) -> io::Result<HashMap<String, i32>> {
+    let mut iters = HashMap::new();
+    for _ in 0..read_len(&mut reader, MAX_ENTRIES)? {
+        let name = read_name(&mut reader)?;
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf)?;
+        iters.insert(name, i32::from_le_bytes(buf));
+    }
+    Ok(iters)
+}
+
+// Counts updates per parameter, so that a parameter updated in a separate call
+// (e.g., by `ParamGroups`) still gets the right bias correction
+fn next_iter(iters: &mut HashMap<String, i32>, name: &str) -> i32 {
+    if !iters.contains_key(name) {
+        iters.insert(name.to_owned(), 0);
+    }
+    let iter = iters.get_mut(name).expect("Never fails");
+    *iter += 1;
+    *iter
+}
+
 #[derive(Debug)]
 pub struct Sgd {
     learning_rate: f64,
@@ -22,6 +139,14 @@ impl Sgd {
     }
 }
 impl Optimizer for Sgd {
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+
     fn update<'a, 'b, I>(&'a mut self, pairs: I)
     where
         I: Iterator<Item = Pairs<'b>>,
@@ -36,29 +161,34 @@ impl Optimizer for Sgd {
 pub struct Momentum {
     learning_rate: f64,
     momentum: f64,
-    v: Vec<Matrix>,
+    v: States,
 }
 impl Momentum {
     pub fn new(learning_rate: f64, momentum: f64) -> Self {
         Momentum {
             learning_rate,
             momentum,
-            v: Vec::new(),
+            v: States::default(),
         }
     }
 }
 impl Optimizer for Momentum {
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+
     fn update<'a, 'b, I>(&'a mut self, pairs: I)
     where
         I: Iterator<Item = Pairs<'b>>,
     {
-        for (i, t) in pairs.enumerate() {
-            if self.v.len() < i {
-                self.v.push(Matrix::new(t.param.rows(), t.param.columns()));
-            }
-            self.v[i] =
-                (self.v[i].clone() * self.momentum).sub(&(t.gradient.clone() * self.learning_rate));
-            *t.param = t.param.clone().add(&self.v[i]);
+        for t in pairs {
+            let v = self.v.get_mut(&t);
+            *v = (v.clone() * self.momentum).sub(&(t.gradient.clone() * self.learning_rate));
+            *t.param = t.param.clone().add(v);
         }
     }
 }
@@ -71,27 +201,1107 @@ impl Default for Momentum {
 #[derive(Debug)]
 pub struct AdaGrad {
     learning_rate: f64,
-    h: Vec<Matrix>,
+    h: States,
 }
 impl AdaGrad {
     pub fn new(learning_rate: f64) -> Self {
         AdaGrad {
             learning_rate,
-            h: Vec::new(),
+            h: States::default(),
         }
     }
 }
 impl Optimizer for AdaGrad {
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+
+    fn update<'a, 'b, I>(&'a mut self, pairs: I)
+    where
+        I: Iterator<Item = Pairs<'b>>,
+    {
+        for t in pairs {
+            let h = self.h.get_mut(&t);
+            *h += t.gradient.clone() * t.gradient.clone();
+            *t.param -= (t.gradient.clone() * self.learning_rate) / (h.sqrt() + 1e-7);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Adam {
+    learning_rate: f64,
+    beta1: f64,
+    beta2: f64,
+    iters: HashMap<String, i32>,
+    m: States,
+    v: States,
+}
+impl Adam {
+    pub fn new(learning_rate: f64, beta1: f64, beta2: f64) -> Self {
+        Adam {
+            learning_rate,
+            beta1,
+            beta2,
+            iters: HashMap::new(),
+            m: States::default(),
+            v: States::default(),
+        }
+    }
+}
+impl Optimizer for Adam {
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+
+    fn update<'a, 'b, I>(&'a mut self, pairs: I)
+    where
+        I: Iterator<Item = Pairs<'b>>,
+    {
+        for t in pairs {
+            // バイアス補正を学習率に含めておく
+            let iter = next_iter(&mut self.iters, t.name);
+            let lr_t = self.learning_rate * (1.0 - self.beta2.powi(iter)).sqrt()
+                / (1.0 - self.beta1.powi(iter));
+
+            let g = t.gradient.clone();
+            let m = self.m.get_mut(&t);
+            let dm = (g.clone().sub(m)) * (1.0 - self.beta1);
+            *m += dm;
+            let v = self.v.get_mut(&t);
+            let dv = ((g.clone() * g).sub(v)) * (1.0 - self.beta2);
+            *v += dv;
+            *t.param -= (m.clone() * lr_t) / (v.sqrt() + 1e-7);
+        }
+    }
+}
+impl Default for Adam {
+    fn default() -> Self {
+        Self::new(0.001, 0.9, 0.999)
+    }
+}
+
+#[derive(Debug)]
+pub struct AdamW {
+    adam: Adam,
+    weight_decay: f64,
+}
+impl AdamW {
+    pub fn new(learning_rate: f64, beta1: f64, beta2: f64, weight_decay: f64) -> Self {
+        AdamW {
+            adam: Adam::new(learning_rate, beta1, beta2),
+            weight_decay,
+        }
+    }
+}
+impl Optimizer for AdamW {
+    fn learning_rate(&self) -> f64 {
+        self.adam.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.adam.learning_rate = learning_rate;
+    }
+
+    fn update<'a, 'b, I>(&'a mut self, pairs: I)
+    where
+        I: Iterator<Item = Pairs<'b>>,
+    {
+        // 勾配とは切り離して、パラメータを直接減衰させる
+        let decay = self.adam.learning_rate * self.weight_decay;
+        let pairs = pairs.map(|t| {
+            *t.param -= t.param.clone() * decay;
+            t
+        });
+        self.adam.update(pairs);
+    }
+}
+impl Default for AdamW {
+    fn default() -> Self {
+        Self::new(0.001, 0.9, 0.999, 0.01)
+    }
+}
+
+#[derive(Debug)]
+pub struct RmsProp {
+    learning_rate: f64,
+    decay_rate: f64,
+    h: States,
+}
+impl RmsProp {
+    pub fn new(learning_rate: f64, decay_rate: f64) -> Self {
+        RmsProp {
+            learning_rate,
+            decay_rate,
+            h: States::default(),
+        }
+    }
+}
+impl Optimizer for RmsProp {
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+
+    fn update<'a, 'b, I>(&'a mut self, pairs: I)
+    where
+        I: Iterator<Item = Pairs<'b>>,
+    {
+        for t in pairs {
+            let g = t.gradient.clone();
+            let h = self.h.get_mut(&t);
+            *h = (h.clone() * self.decay_rate)
+                .add(&((g.clone() * g.clone()) * (1.0 - self.decay_rate)));
+            *t.param -= (g * self.learning_rate) / (h.sqrt() + 1e-7);
+        }
+    }
+}
+impl Default for RmsProp {
+    fn default() -> Self {
+        Self::new(0.01, 0.99)
+    }
+}
+
+#[derive(Debug)]
+pub struct Nesterov {
+    learning_rate: f64,
+    momentum: f64,
+    v: States,
+}
+impl Nesterov {
+    pub fn new(learning_rate: f64, momentum: f64) -> Self {
+        Nesterov {
+            learning_rate,
+            momentum,
+            v: States::default(),
+        }
+    }
+}
+impl Optimizer for Nesterov {
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+
+    fn update<'a, 'b, I>(&'a mut self, pairs: I)
+    where
+        I: Iterator<Item = Pairs<'b>>,
+    {
+        for t in pairs {
+            let g = t.gradient.clone() * self.learning_rate;
+            let v = self.v.get_mut(&t);
+            *v = (v.clone() * self.momentum).sub(&g);
+            *t.param += v.clone() * (self.momentum * self.momentum);
+            *t.param -= g * (1.0 + self.momentum);
+        }
+    }
+}
+impl Default for Nesterov {
+    fn default() -> Self {
+        Self::new(0.01, 0.9)
+    }
+}
+
+#[derive(Debug)]
+pub struct AdaDelta {
+    learning_rate: f64,
+    rho: f64,
+    epsilon: f64,
+    h: States,
+    s: States,
+}
+impl AdaDelta {
+    pub fn new(learning_rate: f64, rho: f64, epsilon: f64) -> Self {
+        AdaDelta {
+            learning_rate,
+            rho,
+            epsilon,
+            h: States::default(),
+            s: States::default(),
+        }
+    }
+}
+impl Optimizer for AdaDelta {
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+
+    fn update<'a, 'b, I>(&'a mut self, pairs: I)
+    where
+        I: Iterator<Item = Pairs<'b>>,
+    {
+        for t in pairs {
+            let g = t.gradient.clone();
+            let h = self.h.get_mut(&t);
+            *h = (h.clone() * self.rho).add(&((g.clone() * g.clone()) * (1.0 - self.rho)));
+
+            // `h`(勾配の二乗)と`s`(更新量の二乗)の比で学習率を決める
+            let s = self.s.get_mut(&t);
+            let dx = (g * (s.clone() + self.epsilon).sqrt()) / (h.clone() + self.epsilon).sqrt();
+            *s = (s.clone() * self.rho).add(&((dx.clone() * dx.clone()) * (1.0 - self.rho)));
+            *t.param -= dx * self.learning_rate;
+        }
+    }
+}
+impl Default for AdaDelta {
+    fn default() -> Self {
+        Self::new(1.0, 0.95, 1e-6)
+    }
+}
+
+#[derive(Debug)]
+pub struct Adamax {
+    learning_rate: f64,
+    beta1: f64,
+    beta2: f64,
+    iters: HashMap<String, i32>,
+    m: States,
+    u: States,
+}
+impl Adamax {
+    pub fn new(learning_rate: f64, beta1: f64, beta2: f64) -> Self {
+        Adamax {
+            learning_rate,
+            beta1,
+            beta2,
+            iters: HashMap::new(),
+            m: States::default(),
+            u: States::default(),
+        }
+    }
+}
+impl Optimizer for Adamax {
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+
     fn update<'a, 'b, I>(&'a mut self, pairs: I)
     where
         I: Iterator<Item = Pairs<'b>>,
     {
-        for (i, t) in pairs.enumerate() {
-            if self.h.len() < i {
-                self.h.push(Matrix::new(t.param.rows(), t.param.columns()));
+        for t in pairs {
+            let iter = next_iter(&mut self.iters, t.name);
+            let lr_t = self.learning_rate / (1.0 - self.beta1.powi(iter));
+
+            let g = t.gradient.clone();
+            let m = self.m.get_mut(&t);
+            *m = (m.clone() * self.beta1).add(&(g.clone() * (1.0 - self.beta1)));
+
+            // 二乗平均の代わりに無限ノルム(最大値)を使う
+            let u = self.u.get_mut(&t);
+            *u = (u.clone() * self.beta2).zip_map(&g, |u, g| u.max(g.abs()));
+            *t.param -= (m.clone() * lr_t) / (u.clone() + 1e-7);
+        }
+    }
+}
+impl Default for Adamax {
+    fn default() -> Self {
+        Self::new(0.002, 0.9, 0.999)
+    }
+}
+
+fn global_norm(pairs: &[Pairs]) -> f64 {
+    pairs
+        .iter()
+        .map(|t| (t.gradient.clone() * t.gradient.clone()).sum())
+        .sum::<f64>()
+        .sqrt()
+}
+
+#[derive(Debug)]
+pub struct GradientNormClipping<O> {
+    inner: O,
+    max_norm: f64,
+    last_norm: f64,
+}
+impl<O: Optimizer> GradientNormClipping<O> {
+    pub fn new(inner: O, max_norm: f64) -> Self {
+        GradientNormClipping {
+            inner,
+            max_norm,
+            last_norm: 0.0,
+        }
+    }
+
+    // The global L2 norm of the gradients passed to the last `update()` (before clipping)
+    pub fn last_norm(&self) -> f64 {
+        self.last_norm
+    }
+
+    pub fn inner(&self) -> &O {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut O {
+        &mut self.inner
+    }
+}
+impl<O: Optimizer> Optimizer for GradientNormClipping<O> {
+    fn learning_rate(&self) -> f64 {
+        self.inner.learning_rate()
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.inner.set_learning_rate(learning_rate);
+    }
+
+    fn update<'a, 'b, I>(&'a mut self, pairs: I)
+    where
+        I: Iterator<Item = Pairs<'b>>,
+    {
+        let pairs = pairs.collect::<Vec<_>>();
+        self.last_norm = global_norm(&pairs);
+
+        let scale = if self.last_norm > self.max_norm {
+            self.max_norm / (self.last_norm + 1e-6)
+        } else {
+            1.0
+        };
+        let gradients = pairs
+            .iter()
+            .map(|t| t.gradient.clone() * scale)
+            .collect::<Vec<_>>();
+        self.inner.update(
+            pairs
+                .into_iter()
+                .zip(&gradients)
+                .map(|(t, gradient)| Pairs {
+                    name: t.name,
+                    param: t.param,
+                    gradient,
+                }),
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct ValueClipping<O> {
+    inner: O,
+    clip_value: f64,
+    last_norm: f64,
+}
+impl<O: Optimizer> ValueClipping<O> {
+    pub fn new(inner: O, clip_value: f64) -> Self {
+        ValueClipping {
+            inner,
+            clip_value,
+            last_norm: 0.0,
+        }
+    }
+
+    // The global L2 norm of the gradients passed to the last `update()` (before clipping)
+    pub fn last_norm(&self) -> f64 {
+        self.last_norm
+    }
+
+    pub fn inner(&self) -> &O {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut O {
+        &mut self.inner
+    }
+}
+impl<O: Optimizer> Optimizer for ValueClipping<O> {
+    fn learning_rate(&self) -> f64 {
+        self.inner.learning_rate()
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.inner.set_learning_rate(learning_rate);
+    }
+
+    fn update<'a, 'b, I>(&'a mut self, pairs: I)
+    where
+        I: Iterator<Item = Pairs<'b>>,
+    {
+        let pairs = pairs.collect::<Vec<_>>();
+        self.last_norm = global_norm(&pairs);
+
+        let c = self.clip_value;
+        let gradients = pairs
+            .iter()
+            .map(|t| t.gradient.clone().map(|&g| g.max(-c).min(c)))
+            .collect::<Vec<_>>();
+        self.inner.update(
+            pairs
+                .into_iter()
+                .zip(&gradients)
+                .map(|(t, gradient)| Pairs {
+                    name: t.name,
+                    param: t.param,
+                    gradient,
+                }),
+        );
+    }
+}
+
+// L2 regularization: adds `lambda * W` to the gradients (i.e., `0.5 * lambda * W^2` to the loss)
+#[derive(Debug)]
+pub struct WeightDecay<O> {
+    inner: O,
+    lambda: f64,
+}
+impl<O: Optimizer> WeightDecay<O> {
+    pub fn new(inner: O, lambda: f64) -> Self {
+        WeightDecay { inner, lambda }
+    }
+
+    // The term to add to the loss so that it agrees with the decayed gradients
+    pub fn penalty<'a, I>(&self, params: I) -> f64
+    where
+        I: Iterator<Item = &'a Matrix>,
+    {
+        params
+            .map(|w| 0.5 * self.lambda * (w.clone() * w.clone()).sum())
+            .sum()
+    }
+
+    pub fn inner(&self) -> &O {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut O {
+        &mut self.inner
+    }
+}
+impl<O: Optimizer> Optimizer for WeightDecay<O> {
+    fn learning_rate(&self) -> f64 {
+        self.inner.learning_rate()
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.inner.set_learning_rate(learning_rate);
+    }
+
+    fn update<'a, 'b, I>(&'a mut self, pairs: I)
+    where
+        I: Iterator<Item = Pairs<'b>>,
+    {
+        let pairs = pairs.collect::<Vec<_>>();
+        let gradients = pairs
+            .iter()
+            .map(|t| t.gradient.clone().add(&(t.param.clone() * self.lambda)))
+            .collect::<Vec<_>>();
+        self.inner.update(
+            pairs
+                .into_iter()
+                .zip(&gradients)
+                .map(|(t, gradient)| Pairs {
+                    name: t.name,
+                    param: t.param,
+                    gradient,
+                }),
+        );
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParamGroup {
+    // Parameter names or name prefixes (e.g., "affine1" matches "affine1.w" and "affine1.b").
+    // An empty list matches every parameter.
+    pub params: Vec<String>,
+
+    // Multiplied by the optimizer's learning rate (`0.0` freezes the group)
+    pub learning_rate_scale: f64,
+
+    // L2 regularization coefficient (see `WeightDecay`)
+    pub weight_decay: f64,
+}
+impl ParamGroup {
+    fn contains(&self, name: &str) -> bool {
+        self.params.is_empty()
+            || self.params.iter().any(|p| {
+                name == p || (name.starts_with(p.as_str()) && name[p.len()..].starts_with('.'))
+            })
+    }
+}
+impl Default for ParamGroup {
+    fn default() -> Self {
+        ParamGroup {
+            params: Vec::new(),
+            learning_rate_scale: 1.0,
+            weight_decay: 0.0,
+        }
+    }
+}
+
+// Parameters belong to the first group that contains them.
+// Parameters that belong to no group are updated by the inner optimizer as is.
+#[derive(Debug)]
+pub struct ParamGroups<O> {
+    inner: O,
+    groups: Vec<ParamGroup>,
+}
+impl<O: Optimizer> ParamGroups<O> {
+    pub fn new(inner: O, groups: Vec<ParamGroup>) -> Self {
+        ParamGroups { inner, groups }
+    }
+
+    pub fn groups(&self) -> &[ParamGroup] {
+        &self.groups
+    }
+
+    pub fn groups_mut(&mut self) -> &mut [ParamGroup] {
+        &mut self.groups
+    }
+
+    pub fn inner(&self) -> &O {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut O {
+        &mut self.inner
+    }
+}
+impl<O: Optimizer> Optimizer for ParamGroups<O> {
+    fn learning_rate(&self) -> f64 {
+        self.inner.learning_rate()
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.inner.set_learning_rate(learning_rate);
+    }
+
+    fn update<'a, 'b, I>(&'a mut self, pairs: I)
+    where
+        I: Iterator<Item = Pairs<'b>>,
+    {
+        let mut grouped = self.groups.iter().map(|_| Vec::new()).collect::<Vec<_>>();
+        let mut ungrouped = Vec::new();
+        for t in pairs {
+            match self.groups.iter().position(|g| g.contains(t.name)) {
+                Some(i) => grouped[i].push(t),
+                None => ungrouped.push(t),
             }
-            self.h[i] += t.gradient.clone() * t.gradient.clone();
-            *t.param -= (t.gradient.clone() * self.learning_rate) / (self.h[i].sqrt() + 1e-7);
         }
+
+        let learning_rate = self.inner.learning_rate();
+        for (group, pairs) in self.groups.iter().zip(grouped) {
+            if pairs.is_empty() || group.learning_rate_scale == 0.0 {
+                continue;
+            }
+
+            let gradients = pairs
+                .iter()
+                .map(|t| {
+                    t.gradient
+                        .clone()
+                        .add(&(t.param.clone() * group.weight_decay))
+                })
+                .collect::<Vec<_>>();
+            self.inner
+                .set_learning_rate(learning_rate * group.learning_rate_scale);
+            self.inner.update(
+                pairs
+                    .into_iter()
+                    .zip(&gradients)
+                    .map(|(t, gradient)| Pairs {
+                        name: t.name,
+                        param: t.param,
+                        gradient,
+                    }),
+            );
+        }
+        self.inner.set_learning_rate(learning_rate);
+        if !ungrouped.is_empty() {
+            self.inner.update(ungrouped.into_iter());
+        }
+    }
+}
+
+// Lookahead Optimizer: https://arxiv.org/abs/1907.08610
+#[derive(Debug)]
+pub struct Lookahead<O> {
+    inner: O,
+    k: i32,
+    alpha: f64,
+    iters: HashMap<String, i32>,
+    slow: States,
+}
+impl<O: Optimizer> Lookahead<O> {
+    pub fn new(inner: O, k: usize, alpha: f64) -> Self {
+        assert_ne!(k, 0);
+        Lookahead {
+            inner,
+            k: i32::try_from(k).expect("Too large k"),
+            alpha,
+            iters: HashMap::new(),
+            slow: States::default(),
+        }
+    }
+
+    pub fn inner(&self) -> &O {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut O {
+        &mut self.inner
+    }
+
+    // Saves the slow weights (the state of `inner` is not included)
+    pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_iters(&mut writer, &self.iters)?;
+        self.slow.write_to(&mut writer)
+    }
+
+    pub fn load<R: Read>(&mut self, mut reader: R) -> io::Result<()> {
+        self.iters = read_iters(&mut reader)?;
+        self.slow = States::read_from(&mut reader)?;
+        Ok(())
+    }
+}
+impl<O: Optimizer> Optimizer for Lookahead<O> {
+    fn learning_rate(&self) -> f64 {
+        self.inner.learning_rate()
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.inner.set_learning_rate(learning_rate);
+    }
+
+    fn update<'a, 'b, I>(&'a mut self, pairs: I)
+    where
+        I: Iterator<Item = Pairs<'b>>,
+    {
+        let mut pairs = pairs.collect::<Vec<_>>();
+        for t in &pairs {
+            self.slow.entry(t, || t.param.clone());
+        }
+
+        self.inner.update(pairs.iter_mut().map(|t| Pairs {
+            name: t.name,
+            param: &mut *t.param,
+            gradient: t.gradient,
+        }));
+
+        // `k`回毎に、遅い重みを速い重みの方向へ`alpha`だけ進めて同期する
+        for t in pairs {
+            if next_iter(&mut self.iters, t.name) % self.k != 0 {
+                continue;
+            }
+            let slow = self.slow.get_mut(&t);
+            let diff = t.param.clone().sub(slow) * self.alpha;
+            *slow += diff;
+            *t.param = slow.clone();
+        }
+    }
+}
+
+// Gradients in `Pairs` are ignored by the weight averaging below
+#[derive(Debug)]
+pub struct ExponentialMovingAverage {
+    decay: f64,
+    averages: States,
+}
+impl ExponentialMovingAverage {
+    pub fn new(decay: f64) -> Self {
+        ExponentialMovingAverage {
+            decay,
+            averages: States::default(),
+        }
+    }
+
+    pub fn update<'a, I>(&mut self, pairs: I)
+    where
+        I: Iterator<Item = Pairs<'a>>,
+    {
+        for t in pairs {
+            let average = self.averages.entry(&t, || t.param.clone());
+            *average = (average.clone() * self.decay).add(&(t.param.clone() * (1.0 - self.decay)));
+        }
+    }
+
+    // Exchanges the parameters and their averages (call again to restore the parameters)
+    pub fn swap<'a, I>(&mut self, pairs: I)
+    where
+        I: Iterator<Item = Pairs<'a>>,
+    {
+        for t in pairs {
+            let average = self.averages.entry(&t, || t.param.clone());
+            mem::swap(t.param, average);
+        }
+    }
+
+    pub fn save<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.averages.write_to(writer)
+    }
+
+    pub fn load<R: Read>(&mut self, reader: R) -> io::Result<()> {
+        self.averages = States::read_from(reader)?;
+        Ok(())
+    }
+}
+
+// SWA: https://arxiv.org/abs/1803.05407
+#[derive(Debug, Default)]
+pub struct StochasticWeightAveraging {
+    counts: HashMap<String, i32>,
+    averages: States,
+}
+impl StochasticWeightAveraging {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update<'a, I>(&mut self, pairs: I)
+    where
+        I: Iterator<Item = Pairs<'a>>,
+    {
+        for t in pairs {
+            let n = next_iter(&mut self.counts, t.name);
+            let average = self.averages.get_mut(&t);
+            let diff = t.param.clone().sub(average) / f64::from(n);
+            *average += diff;
+        }
+    }
+
+    // Exchanges the parameters and their averages (call again to restore the parameters)
+    pub fn swap<'a, I>(&mut self, pairs: I)
+    where
+        I: Iterator<Item = Pairs<'a>>,
+    {
+        for t in pairs {
+            let average = self.averages.entry(&t, || t.param.clone());
+            mem::swap(t.param, average);
+        }
+    }
+
+    pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_iters(&mut writer, &self.counts)?;
+        self.averages.write_to(&mut writer)
+    }
+
+    pub fn load<R: Read>(&mut self, mut reader: R) -> io::Result<()> {
+        self.counts = read_iters(&mut reader)?;
+        self.averages = States::read_from(&mut reader)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::iter::once;
+
+    // f(x, y) = x^2 / 20 + y^2
+    fn minimize<O: Optimizer>(optimizer: &mut O, step_num: usize) -> Vec<f64> {
+        let mut param = Matrix::from(vec![vec![-7.0, 2.0]]);
+        for _ in 0..step_num {
+            let gradient = {
+                let xs = param.row_slice(0);
+                Matrix::from(vec![vec![xs[0] / 10.0, 2.0 * xs[1]]])
+            };
+            optimizer.update(once(Pairs {
+                name: "x",
+                param: &mut param,
+                gradient: &gradient,
+            }));
+        }
+        param.into_vector()
+    }
+
+    fn assert_converged(xs: &[f64]) {
+        assert!(xs.iter().all(|x| x.abs() < 0.01), "{:?}", xs);
+    }
+
+    #[test]
+    fn sgd_works() {
+        assert_converged(&minimize(&mut Sgd::new(0.95), 1000));
+    }
+
+    #[test]
+    fn momentum_works() {
+        assert_converged(&minimize(&mut Momentum::new(0.1, 0.9), 1000));
+    }
+
+    #[test]
+    fn ada_grad_works() {
+        assert_converged(&minimize(&mut AdaGrad::new(1.5), 1000));
+    }
+
+    #[test]
+    fn adam_works() {
+        assert_converged(&minimize(&mut Adam::new(0.3, 0.9, 0.999), 1000));
+    }
+
+    #[test]
+    fn adamw_works() {
+        assert_converged(&minimize(&mut AdamW::new(0.3, 0.9, 0.999, 0.01), 1000));
+    }
+
+    #[test]
+    fn rms_prop_works() {
+        assert_converged(&minimize(&mut RmsProp::new(0.1, 0.99), 1000));
+    }
+
+    #[test]
+    fn nesterov_works() {
+        assert_converged(&minimize(&mut Nesterov::new(0.1, 0.9), 1000));
+    }
+
+    #[test]
+    fn ada_delta_works() {
+        assert_converged(&minimize(&mut AdaDelta::new(1.0, 0.95, 1e-2), 1000));
+    }
+
+    #[test]
+    fn adamax_works() {
+        assert_converged(&minimize(&mut Adamax::new(0.3, 0.9, 0.999), 1000));
+    }
+
+    #[test]
+    fn state_follows_parameter_names() {
+        let gradients = [
+            Matrix::from(vec![vec![1.0, -1.0]]),
+            Matrix::from(vec![vec![0.5]]),
+        ];
+        let mut forward = vec![
+            Matrix::from(vec![vec![1.0, 2.0]]),
+            Matrix::from(vec![vec![3.0]]),
+        ];
+        let mut backward = forward.clone();
+
+        let mut optimizer0 = Momentum::default();
+        let mut optimizer1 = Momentum::default();
+        for _ in 0..3 {
+            {
+                let (w, b) = forward.split_at_mut(1);
+                optimizer0.update(
+                    vec![
+                        Pairs {
+                            name: "w",
+                            param: &mut w[0],
+                            gradient: &gradients[0],
+                        },
+                        Pairs {
+                            name: "b",
+                            param: &mut b[0],
+                            gradient: &gradients[1],
+                        },
+                    ]
+                    .into_iter(),
+                );
+            }
+            {
+                let (w, b) = backward.split_at_mut(1);
+                optimizer1.update(
+                    vec![
+                        Pairs {
+                            name: "b",
+                            param: &mut b[0],
+                            gradient: &gradients[1],
+                        },
+                        Pairs {
+                            name: "w",
+                            param: &mut w[0],
+                            gradient: &gradients[0],
+                        },
+                    ]
+                    .into_iter(),
+                );
+            }
+        }
+        assert_eq!(forward, backward);
+    }
+
+    fn update_once<O: Optimizer>(optimizer: &mut O, param: &mut Matrix, gradient: &Matrix) {
+        optimizer.update(once(Pairs {
+            name: "x",
+            param,
+            gradient,
+        }));
+    }
+
+    #[test]
+    fn gradient_norm_clipping_works() {
+        let mut optimizer = GradientNormClipping::new(Sgd::new(1.0), 1.0);
+        let mut param = Matrix::from(vec![vec![0.0, 0.0]]);
+        update_once(
+            &mut optimizer,
+            &mut param,
+            &Matrix::from(vec![vec![3.0, 4.0]]),
+        );
+        assert_eq!(optimizer.last_norm(), 5.0);
+        let xs = param.into_vector();
+        assert!((xs[0] + 0.6).abs() < 1e-6, "{:?}", xs);
+        assert!((xs[1] + 0.8).abs() < 1e-6, "{:?}", xs);
+    }
+
+    #[test]
+    fn value_clipping_works() {
+        let mut optimizer = ValueClipping::new(Sgd::new(1.0), 1.0);
+        let mut param = Matrix::from(vec![vec![0.0, 0.0, 0.0]]);
+        update_once(
+            &mut optimizer,
+            &mut param,
+            &Matrix::from(vec![vec![3.0, -4.0, 0.5]]),
+        );
+        assert_eq!(param.into_vector(), [-1.0, 1.0, -0.5]);
+    }
+
+    #[test]
+    fn weight_decay_works() {
+        let mut optimizer = WeightDecay::new(Sgd::new(1.0), 0.1);
+        let mut param = Matrix::from(vec![vec![1.0, 2.0]]);
+        assert_eq!(optimizer.penalty(once(&param)), 0.25);
+
+        update_once(&mut optimizer, &mut param, &Matrix::new(1, 2));
+        assert_eq!(param.into_vector(), [0.9, 1.8]);
+    }
+
+    #[test]
+    fn param_groups_works() {
+        let groups = vec![
+            ParamGroup {
+                params: vec!["backbone".to_owned()],
+                learning_rate_scale: 0.0,
+                ..Default::default()
+            },
+            ParamGroup {
+                params: vec!["head.b".to_owned()],
+                learning_rate_scale: 2.0,
+                ..Default::default()
+            },
+            ParamGroup {
+                weight_decay: 0.5,
+                ..Default::default()
+            },
+        ];
+        let mut optimizer = ParamGroups::new(Sgd::new(0.1), groups);
+
+        let gradient = Matrix::from(vec![vec![1.0]]);
+        let mut params = vec![Matrix::from(vec![vec![1.0]]); 4];
+        let names = ["backbone.w", "backbone2.w", "head.b", "head.w"];
+        optimizer.update(
+            names
+                .iter()
+                .zip(params.iter_mut())
+                .map(|(name, param)| Pairs {
+                    name,
+                    param,
+                    gradient: &gradient,
+                }),
+        );
+
+        let params = params
+            .into_iter()
+            .map(|p| p.into_vector()[0])
+            .collect::<Vec<_>>();
+        assert_eq!(params, [1.0, 0.85, 0.8, 0.85]);
+        assert_eq!(optimizer.learning_rate(), 0.1);
+    }
+
+    #[test]
+    fn lookahead_works() {
+        let mut optimizer = Lookahead::new(Sgd::new(1.0), 2, 0.5);
+        let mut param = Matrix::from(vec![vec![0.0]]);
+        let gradient = Matrix::from(vec![vec![1.0]]);
+        update_once(&mut optimizer, &mut param, &gradient);
+        assert_eq!(param.row_slice(0), [-1.0]);
+        update_once(&mut optimizer, &mut param, &gradient);
+        assert_eq!(param.row_slice(0), [-1.0]);
+
+        assert_converged(&minimize(&mut Lookahead::new(Sgd::new(0.95), 5, 0.5), 1000));
+    }
+
+    #[test]
+    fn lookahead_save_and_load_works() {
+        let gradient = Matrix::from(vec![vec![1.0]]);
+        let mut param0 = Matrix::from(vec![vec![0.0]]);
+        let mut optimizer0 = Lookahead::new(Sgd::new(1.0), 2, 0.5);
+        update_once(&mut optimizer0, &mut param0, &gradient);
+
+        let mut buf = Vec::new();
+        optimizer0.save(&mut buf).unwrap();
+        let mut param1 = param0.clone();
+        let mut optimizer1 = Lookahead::new(Sgd::new(1.0), 2, 0.5);
+        optimizer1.load(&buf[..]).unwrap();
+
+        update_once(&mut optimizer0, &mut param0, &gradient);
+        update_once(&mut optimizer1, &mut param1, &gradient);
+        assert_eq!(param0, param1);
+
+        // A corrupt entry count or name length fails without allocating it
+        let mut corrupt = buf.clone();
+        corrupt[..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let e = optimizer1.load(&corrupt[..]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        let mut corrupt = buf.clone();
+        corrupt[8..16].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        let e = optimizer1.load(&corrupt[..]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(optimizer1.load(&buf[..buf.len() - 1]).is_err());
+    }
+
+    fn pairs<'a>(param: &'a mut Matrix, gradient: &'a Matrix) -> impl Iterator<Item = Pairs<'a>> {
+        once(Pairs {
+            name: "x",
+            param,
+            gradient,
+        })
+    }
+
+    #[test]
+    fn exponential_moving_average_works() {
+        let gradient = Matrix::new(1, 1);
+        let mut ema = ExponentialMovingAverage::new(0.5);
+        let mut param = Matrix::from(vec![vec![0.0]]);
+        ema.update(pairs(&mut param, &gradient));
+        param = Matrix::from(vec![vec![2.0]]);
+        ema.update(pairs(&mut param, &gradient));
+
+        let mut buf = Vec::new();
+        ema.save(&mut buf).unwrap();
+        let mut ema = ExponentialMovingAverage::new(0.5);
+        ema.load(&buf[..]).unwrap();
+
+        ema.swap(pairs(&mut param, &gradient));
+        assert_eq!(param.row_slice(0), [1.0]);
+        ema.swap(pairs(&mut param, &gradient));
+        assert_eq!(param.row_slice(0), [2.0]);
+    }
+
+    #[test]
+    fn stochastic_weight_averaging_works() {
+        let gradient = Matrix::new(1, 1);
+        let mut swa = StochasticWeightAveraging::new();
+        let mut param = Matrix::new(1, 1);
+        for &x in &[1.0, 2.0, 6.0] {
+            param = Matrix::from(vec![vec![x]]);
+            swa.update(pairs(&mut param, &gradient));
+        }
+
+        let mut buf = Vec::new();
+        swa.save(&mut buf).unwrap();
+        let mut swa = StochasticWeightAveraging::new();
+        swa.load(&buf[..]).unwrap();
+
+        swa.swap(pairs(&mut param, &gradient));
+        assert_eq!(param.row_slice(0), [3.0]);
+        swa.swap(pairs(&mut param, &gradient));
+        assert_eq!(param.row_slice(0), [6.0]);
     }
 }
@@ -0,0 +1,592 @@
+use std::io::{self, Read, Write};
+
+use matrix::Matrix;
+
+// Rows of the input matrices are samples.
+// Fit on the training data and then apply the same (fitted) preprocessor to every split.
+//
+// `fit` fails on empty inputs, and `transform` fails on inputs whose width does not match
+// the fitted statistics (both with `InvalidInput`).
+pub trait Preprocessor {
+    fn fit(&mut self, x: &Matrix) -> io::Result<()>;
+
+    fn transform(&self, x: &Matrix) -> io::Result<Matrix>;
+
+    fn fit_transform(&mut self, x: &Matrix) -> io::Result<Matrix> {
+        self.fit(x)?;
+        self.transform(x)
+    }
+
+    // Saves the fitted statistics (the options given to the constructor are not included)
+    fn save<W: Write>(&self, writer: W) -> io::Result<()>;
+
+    fn load<R: Read>(&mut self, reader: R) -> io::Result<()>;
+}
+
+// Scales each feature into `[min, max]`
+#[derive(Debug, Clone)]
+pub struct MinMaxScaler {
+    min: f64,
+    max: f64,
+    data_min: Vec<f64>,
+    data_range: Vec<f64>,
+}
+impl MinMaxScaler {
+    pub fn new(min: f64, max: f64) -> Self {
+        assert!(min < max);
+        MinMaxScaler {
+            min,
+            max,
+            data_min: Vec::new(),
+            data_range: Vec::new(),
+        }
+    }
+}
+impl Default for MinMaxScaler {
+    fn default() -> Self {
+        MinMaxScaler::new(0.0, 1.0)
+    }
+}
+impl Preprocessor for MinMaxScaler {
+    fn fit(&mut self, x: &Matrix) -> io::Result<()> {
+        check_nonempty(x)?;
+        self.data_min.clear();
+        self.data_range.clear();
+        for i in 0..x.columns() {
+            let min = x.column(i).cloned().fold(f64::INFINITY, f64::min);
+            let max = x.column(i).cloned().fold(f64::NEG_INFINITY, f64::max);
+            self.data_min.push(min);
+            self.data_range.push(nonzero(max - min));
+        }
+        Ok(())
+    }
+
+    fn transform(&self, x: &Matrix) -> io::Result<Matrix> {
+        check_columns(x, self.data_min.len())?;
+        let scale = self.max - self.min;
+        Ok(x.clone().map_row(|row| {
+            row.iter()
+                .enumerate()
+                .map(|(i, v)| (v - self.data_min[i]) / self.data_range[i] * scale + self.min)
+                .collect()
+        }))
+    }
+
+    fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_vector(&mut writer, &self.data_min)?;
+        write_vector(&mut writer, &self.data_range)
+    }
+
+    fn load<R: Read>(&mut self, mut reader: R) -> io::Result<()> {
+        let data_min = read_vector(&mut reader)?;
+        let data_range = read_vector(&mut reader)?;
+        check_loaded_len(&data_range, data_min.len())?;
+        self.data_min = data_min;
+        self.data_range = data_range;
+        Ok(())
+    }
+}
+
+// Z-score normalization
+#[derive(Debug, Clone)]
+pub struct Standardizer {
+    channels: Option<usize>,
+    mean: Vec<f64>,
+    std: Vec<f64>,
+}
+impl Standardizer {
+    pub fn per_feature() -> Self {
+        Standardizer {
+            channels: None,
+            mean: Vec::new(),
+            std: Vec::new(),
+        }
+    }
+
+    // Each row is an image flattened in the (channel, height, width) order,
+    // and all pixels of the same channel share the statistics
+    pub fn per_channel(channels: usize) -> Self {
+        assert_ne!(channels, 0);
+        Standardizer {
+            channels: Some(channels),
+            ..Self::per_feature()
+        }
+    }
+
+    pub fn mean(&self) -> &[f64] {
+        &self.mean
+    }
+
+    pub fn std(&self) -> &[f64] {
+        &self.std
+    }
+
+    // The number of statistics for `x` (i.e., the channels or the columns)
+    fn groups(&self, x: &Matrix) -> io::Result<usize> {
+        match self.channels {
+            None => Ok(x.columns()),
+            Some(channels) if x.columns() % channels == 0 => Ok(channels),
+            Some(channels) => invalid_input(format!(
+                "{} columns cannot be split into {} channels",
+                x.columns(),
+                channels
+            )),
+        }
+    }
+
+    // The index of the statistics for the `i`-th column (`columns` must be checked by `groups`)
+    fn group(&self, columns: usize, i: usize) -> usize {
+        match self.channels {
+            None => i,
+            Some(channels) => i / (columns / channels),
+        }
+    }
+}
+impl Preprocessor for Standardizer {
+    fn fit(&mut self, x: &Matrix) -> io::Result<()> {
+        check_nonempty(x)?;
+        let groups = self.groups(x)?;
+        let mut sums = vec![0.0; groups];
+        let mut square_sums = vec![0.0; groups];
+        let mut counts = vec![0.0; groups];
+        for i in 0..x.rows() {
+            for (j, v) in x.row(i).enumerate() {
+                let g = self.group(x.columns(), j);
+                sums[g] += v;
+                square_sums[g] += v * v;
+                counts[g] += 1.0;
+            }
+        }
+        self.mean = sums.iter().zip(&counts).map(|(s, n)| s / n).collect();
+        self.std = square_sums
+            .iter()
+            .zip(&counts)
+            .zip(&self.mean)
+            .map(|((s, n), m)| nonzero((s / n - m * m).max(0.0).sqrt()))
+            .collect();
+        Ok(())
+    }
+
+    fn transform(&self, x: &Matrix) -> io::Result<Matrix> {
+        let groups = self.groups(x)?;
+        if groups != self.mean.len() {
+            return invalid_input(format!(
+                "Expected {} features or channels, but got {} (not fitted?)",
+                self.mean.len(),
+                groups
+            ));
+        }
+        let columns = x.columns();
+        Ok(x.clone().map_row(|row| {
+            row.iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let g = self.group(columns, i);
+                    (v - self.mean[g]) / self.std[g]
+                })
+                .collect()
+        }))
+    }
+
+    fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_vector(&mut writer, &self.mean)?;
+        write_vector(&mut writer, &self.std)
+    }
+
+    fn load<R: Read>(&mut self, mut reader: R) -> io::Result<()> {
+        let mean = read_vector(&mut reader)?;
+        let std = read_vector(&mut reader)?;
+        check_loaded_len(&std, mean.len())?;
+        if let Some(channels) = self.channels {
+            check_loaded_len(&mean, channels)?;
+        }
+        self.mean = mean;
+        self.std = std;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WhiteningKind {
+    // Projects onto the principal components (optionally keeping only the first `components`)
+    Pca,
+
+    // Rotates the PCA whitened data back to the original space
+    Zca,
+}
+
+// Decorrelates the features and scales them to unit variance.
+// The covariance matrix is diagonalized with the Jacobi method (`O(d^3)` per sweep),
+// so fitting takes a while for large inputs such as raw MNIST images.
+#[derive(Debug, Clone)]
+pub struct Whitening {
+    kind: WhiteningKind,
+    epsilon: f64,
+    components: Option<usize>,
+    mean: Vec<f64>,
+    weight: Matrix,
+}
+impl Whitening {
+    pub fn pca(components: Option<usize>) -> Self {
+        Self::new(WhiteningKind::Pca, components)
+    }
+
+    pub fn zca() -> Self {
+        Self::new(WhiteningKind::Zca, None)
+    }
+
+    fn new(kind: WhiteningKind, components: Option<usize>) -> Self {
+        Whitening {
+            kind,
+            epsilon: 1e-5,
+            components,
+            mean: Vec::new(),
+            weight: Matrix::new(0, 0),
+        }
+    }
+
+    // Added to the eigenvalues to avoid amplifying noise in low-variance directions
+    pub fn epsilon(mut self, epsilon: f64) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+
+    pub fn kind(&self) -> WhiteningKind {
+        self.kind
+    }
+}
+impl Preprocessor for Whitening {
+    fn fit(&mut self, x: &Matrix) -> io::Result<()> {
+        check_nonempty(x)?;
+        let (n, d) = x.shape();
+        self.mean = (0..d)
+            .map(|j| x.column(j).sum::<f64>() / n as f64)
+            .collect();
+
+        let mut covariance = vec![vec![0.0; d]; d];
+        for i in 0..n {
+            let row = x.row_slice(i);
+            for (p, c) in covariance.iter_mut().enumerate() {
+                let a = row[p] - self.mean[p];
+                for (q, c) in c.iter_mut().enumerate() {
+                    *c += a * (row[q] - self.mean[q]);
+                }
+            }
+        }
+        for c in covariance.iter_mut().flatten() {
+            *c /= n as f64;
+        }
+
+        let (values, vectors) = symmetric_eigen(covariance);
+        let k = self.components.map_or(d, |k| k.min(d));
+        let scales = values
+            .iter()
+            .take(k)
+            .map(|v| 1.0 / (v.max(0.0) + self.epsilon).sqrt())
+            .collect::<Vec<_>>();
+
+        // PCA: U_k * diag(scales), ZCA: U * diag(scales) * U^T
+        let pca = (0..d)
+            .map(|i| (0..k).map(|j| vectors[i][j] * scales[j]).collect())
+            .collect::<Vec<_>>();
+        self.weight = match self.kind {
+            WhiteningKind::Pca => Matrix::from(pca),
+            WhiteningKind::Zca => {
+                let u_t = (0..k)
+                    .map(|j| (0..d).map(|i| vectors[i][j]).collect())
+                    .collect::<Vec<_>>();
+                Matrix::from(pca).dot_product(&Matrix::from(u_t))
+            }
+        };
+        Ok(())
+    }
+
+    fn transform(&self, x: &Matrix) -> io::Result<Matrix> {
+        check_columns(x, self.mean.len())?;
+        let centered = x
+            .clone()
+            .map_row(|row| row.iter().zip(&self.mean).map(|(v, m)| v - m).collect());
+        Ok(centered.dot_product(&self.weight))
+    }
+
+    fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_vector(&mut writer, &self.mean)?;
+        self.weight.write_to(writer)
+    }
+
+    fn load<R: Read>(&mut self, mut reader: R) -> io::Result<()> {
+        let mean = read_vector(&mut reader)?;
+        let weight = Matrix::read_from(reader)?;
+        // PCA may drop components, but ZCA maps back to the original features
+        let columns_ok = match self.kind {
+            WhiteningKind::Pca => weight.columns() <= mean.len(),
+            WhiteningKind::Zca => weight.columns() == mean.len(),
+        };
+        if weight.rows() != mean.len() || !columns_ok {
+            let message = format!(
+                "A {:?} weight does not match {} features",
+                weight.shape(),
+                mean.len()
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        self.mean = mean;
+        self.weight = weight;
+        Ok(())
+    }
+}
+
+// Constant features are left unscaled
+fn nonzero(scale: f64) -> f64 {
+    if scale > 0.0 {
+        scale
+    } else {
+        1.0
+    }
+}
+
+fn check_nonempty(x: &Matrix) -> io::Result<()> {
+    if x.rows() == 0 || x.columns() == 0 {
+        return invalid_input(format!("Cannot fit on an empty {:?} matrix", x.shape()));
+    }
+    Ok(())
+}
+
+fn check_columns(x: &Matrix, expected: usize) -> io::Result<()> {
+    if x.columns() != expected {
+        return invalid_input(format!(
+            "Expected {} columns, but got {} (not fitted?)",
+            expected,
+            x.columns()
+        ));
+    }
+    Ok(())
+}
+
+fn check_loaded_len(vector: &[f64], expected: usize) -> io::Result<()> {
+    if vector.len() != expected {
+        let message = format!(
+            "Expected {} saved statistics, but got {}",
+            expected,
+            vector.len()
+        );
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    Ok(())
+}
+
+fn invalid_input<T>(message: String) -> io::Result<T> {
+    Err(io::Error::new(io::ErrorKind::InvalidInput, message))
+}
+
+fn write_vector<W: Write>(writer: W, vector: &[f64]) -> io::Result<()> {
+    Matrix::from(vec![vector.to_vec()]).write_to(writer)
+}
+
+fn read_vector<R: Read>(reader: R) -> io::Result<Vec<f64>> {
+    let m = Matrix::read_from(reader)?;
+    if m.rows() != 1 {
+        let message = format!("Expected a row vector, but got {:?} matrix", m.shape());
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    Ok(m.into_vector())
+}
+
+// Returns the eigenvalues in descending order and the eigenvectors as columns (i.e., `vectors[i][j]`
+// is the `i`-th element of the `j`-th eigenvector) using the cyclic Jacobi method
+fn symmetric_eigen(mut a: Vec<Vec<f64>>) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let n = a.len();
+    let mut v = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect::<Vec<Vec<f64>>>();
+    for _ in 0..100 {
+        let off_diagonal = (0..n)
+            .flat_map(|p| (0..n).filter(move |&q| q != p).map(move |q| (p, q)))
+            .map(|(p, q)| a[p][q] * a[p][q])
+            .sum::<f64>();
+        let diagonal = (0..n).map(|p| a[p][p] * a[p][p]).sum::<f64>();
+        if off_diagonal <= 1e-30 * diagonal.max(1e-300) {
+            break;
+        }
+
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[p][q] == 0.0 {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in a.iter_mut().chain(v.iter_mut()) {
+                    let (kp, kq) = (row[p], row[q]);
+                    row[p] = c * kp - s * kq;
+                    row[q] = s * kp + c * kq;
+                }
+                let (upper, lower) = a.split_at_mut(q);
+                for (pk, qk) in upper[p].iter_mut().zip(lower[0].iter_mut()) {
+                    let (x, y) = (*pk, *qk);
+                    *pk = c * x - s * y;
+                    *qk = s * x + c * y;
+                }
+            }
+        }
+    }
+
+    let mut order = (0..n).collect::<Vec<_>>();
+    order.sort_by(|&i, &j| a[j][j].partial_cmp(&a[i][i]).expect("NaN"));
+    let values = order.iter().map(|&j| a[j][j]).collect();
+    let vectors = v
+        .iter()
+        .map(|row| order.iter().map(|&j| row[j]).collect())
+        .collect();
+    (values, vectors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> Matrix {
+        Matrix::from(vec![
+            vec![1.0, 2.0, 0.5],
+            vec![2.0, 3.9, 0.1],
+            vec![3.0, 6.1, -0.4],
+            vec![4.0, 8.2, 0.3],
+            vec![5.0, 9.8, -0.2],
+        ])
+    }
+
+    fn covariance(x: &Matrix) -> Matrix {
+        let n = x.rows() as f64;
+        let mean = (0..x.columns())
+            .map(|j| x.column(j).sum::<f64>() / n)
+            .collect::<Vec<_>>();
+        let centered = x
+            .clone()
+            .map_row(|row| row.iter().zip(&mean).map(|(v, m)| v - m).collect());
+        centered.transpose().dot_product(&centered) / n
+    }
+
+    fn assert_identity(m: &Matrix, tolerance: f64) {
+        for i in 0..m.rows() {
+            for (j, v) in m.row(i).enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((v - expected).abs() < tolerance, "{:?}", m);
+            }
+        }
+    }
+
+    #[test]
+    fn min_max_scaler_works() {
+        let mut scaler = MinMaxScaler::new(-1.0, 1.0);
+        let y = scaler.fit_transform(&samples()).unwrap();
+        assert_eq!(y.row_slice(0)[0], -1.0);
+        assert_eq!(y.row_slice(4)[0], 1.0);
+        assert_eq!(y.row_slice(2)[0], 0.0);
+
+        let test = Matrix::from(vec![vec![7.0, 2.0, 0.5]]);
+        assert_eq!(scaler.transform(&test).unwrap().row_slice(0)[0], 2.0);
+        assert!(scaler.transform(&Matrix::new(1, 2)).is_err());
+        assert!(scaler.fit(&Matrix::new(0, 3)).is_err());
+    }
+
+    #[test]
+    fn standardizer_works() {
+        let mut s = Standardizer::per_feature();
+        let y = s.fit_transform(&samples()).unwrap();
+        for j in 0..3 {
+            let mean = y.column(j).sum::<f64>() / 5.0;
+            let var = y.column(j).map(|v| v * v).sum::<f64>() / 5.0;
+            assert!(mean.abs() < 1e-12);
+            assert!((var - 1.0).abs() < 1e-12);
+        }
+
+        // 2 channels of 2 pixels
+        let x = Matrix::from(vec![vec![0.0, 2.0, 10.0, 10.0], vec![2.0, 4.0, 30.0, 30.0]]);
+        let mut s = Standardizer::per_channel(2);
+        let y = s.fit_transform(&x).unwrap();
+        assert_eq!(s.mean(), [2.0, 20.0]);
+        assert_eq!(s.std(), [2.0_f64.sqrt(), 10.0]);
+        assert_eq!(y.row_slice(1)[2..], [1.0, 1.0]);
+
+        // Widths that cannot be split into the channels (including narrower than the channels)
+        assert!(s.transform(&Matrix::new(1, 3)).is_err());
+        assert!(s.transform(&Matrix::new(1, 1)).is_err());
+        assert!(Standardizer::per_channel(3)
+            .fit(&Matrix::new(2, 2))
+            .is_err());
+        assert!(Standardizer::per_feature().fit(&Matrix::new(0, 4)).is_err());
+    }
+
+    #[test]
+    fn symmetric_eigen_works() {
+        let a = vec![
+            vec![4.0, 1.0, 2.0],
+            vec![1.0, 3.0, 0.0],
+            vec![2.0, 0.0, 5.0],
+        ];
+        let (values, vectors) = symmetric_eigen(a.clone());
+        assert!(values.windows(2).all(|w| w[0] >= w[1]));
+        for (j, value) in values.iter().enumerate() {
+            for i in 0..3 {
+                let av = (0..3).map(|k| a[i][k] * vectors[k][j]).sum::<f64>();
+                assert!((av - value * vectors[i][j]).abs() < 1e-10);
+            }
+        }
+    }
+
+    #[test]
+    fn whitening_works() {
+        let x = samples();
+        let mut pca = Whitening::pca(None).epsilon(0.0);
+        let y = pca.fit_transform(&x).unwrap();
+        assert_identity(&covariance(&y), 1e-8);
+
+        let mut pca = Whitening::pca(Some(2));
+        assert_eq!(pca.fit_transform(&x).unwrap().shape(), (5, 2));
+        assert!(pca.fit(&Matrix::new(0, 3)).is_err());
+
+        let mut zca = Whitening::zca().epsilon(0.0);
+        let y = zca.fit_transform(&x).unwrap();
+        assert_identity(&covariance(&y), 1e-8);
+
+        // ZCA keeps the whitened data close to the original (i.e., the weight is symmetric)
+        let w = zca.weight.clone();
+        for i in 0..3 {
+            for j in 0..3 {
+                assert!((w.row_slice(i)[j] - w.row_slice(j)[i]).abs() < 1e-8);
+            }
+        }
+    }
+
+    #[test]
+    fn save_and_load_works() {
+        let x = samples();
+        let mut zca = Whitening::zca();
+        zca.fit(&x).unwrap();
+        let mut buf = Vec::new();
+        zca.save(&mut buf).unwrap();
+        let mut loaded = Whitening::zca();
+        loaded.load(&buf[..]).unwrap();
+        assert_eq!(loaded.transform(&x).unwrap(), zca.transform(&x).unwrap());
+
+        // A weight that does not match the mean
+        let mut mismatched = Vec::new();
+        write_vector(&mut mismatched, &[0.0; 2]).unwrap();
+        Matrix::new(3, 3).write_to(&mut mismatched).unwrap();
+        assert!(loaded.load(&mismatched[..]).is_err());
+        assert_eq!(loaded.transform(&x).unwrap(), zca.transform(&x).unwrap());
+
+        let mut s = Standardizer::per_channel(3);
+        s.fit(&x).unwrap();
+        let mut buf = Vec::new();
+        s.save(&mut buf).unwrap();
+        let mut loaded = Standardizer::per_channel(3);
+        loaded.load(&buf[..]).unwrap();
+        assert_eq!(loaded.transform(&x).unwrap(), s.transform(&x).unwrap());
+
+        assert!(loaded.load(&buf[..3]).is_err());
+        assert!(Standardizer::per_channel(2).load(&buf[..]).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use rand::rngs::StdRng;
+use rand::{FromEntropy, SeedableRng};
+use std::cell::RefCell;
+
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+// Reseeds the generator that the crate uses (on the current thread) unless an explicit one is given.
+// e.g., weight initialization, batch sampling and shuffling.
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+pub fn with_rng<F, T>(f: F) -> T
+where
+    F: FnOnce(&mut StdRng) -> T,
+{
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+// Derives an independent generator, e.g., for a component that keeps its own generator
+pub fn new_rng() -> StdRng {
+    with_rng(|rng| StdRng::from_rng(rng).expect("Never fails"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn seed_works() {
+        seed(1);
+        let xs = (0..3)
+            .map(|_| with_rng(|r| r.gen::<u64>()))
+            .collect::<Vec<_>>();
+        let a = new_rng().gen::<u64>();
+
+        seed(1);
+        let ys = (0..3)
+            .map(|_| with_rng(|r| r.gen::<u64>()))
+            .collect::<Vec<_>>();
+        let b = new_rng().gen::<u64>();
+        assert_eq!(xs, ys);
+        assert_eq!(a, b);
+    }
+}
@@ -0,0 +1,298 @@
+use std::f64::consts::PI;
+
+use optimize::Optimizer;
+
+// Usage: `scheduler.apply(&mut optimizer)` -> `optimizer.update(..)` -> `scheduler.step(loss)`
+pub trait LrScheduler {
+    // The learning rate for the current step
+    fn learning_rate(&self) -> f64;
+
+    // Advances the schedule by one step (an iteration or an epoch, as the caller decides)
+    fn step(&mut self, loss: f64);
+
+    fn apply<O: Optimizer>(&self, optimizer: &mut O)
+    where
+        Self: Sized,
+    {
+        optimizer.set_learning_rate(self.learning_rate());
+    }
+}
+
+#[derive(Debug)]
+pub struct StepDecay {
+    initial_learning_rate: f64,
+    step_size: usize,
+    gamma: f64,
+    steps: usize,
+}
+impl StepDecay {
+    pub fn new(initial_learning_rate: f64, step_size: usize, gamma: f64) -> Self {
+        assert_ne!(step_size, 0);
+        StepDecay {
+            initial_learning_rate,
+            step_size,
+            gamma,
+            steps: 0,
+        }
+    }
+}
+impl LrScheduler for StepDecay {
+    fn learning_rate(&self) -> f64 {
+        self.initial_learning_rate * self.gamma.powi((self.steps / self.step_size) as i32)
+    }
+
+    fn step(&mut self, _loss: f64) {
+        self.steps += 1;
+    }
+}
+
+#[derive(Debug)]
+pub struct ExponentialDecay {
+    initial_learning_rate: f64,
+    gamma: f64,
+    steps: usize,
+}
+impl ExponentialDecay {
+    pub fn new(initial_learning_rate: f64, gamma: f64) -> Self {
+        ExponentialDecay {
+            initial_learning_rate,
+            gamma,
+            steps: 0,
+        }
+    }
+}
+impl LrScheduler for ExponentialDecay {
+    fn learning_rate(&self) -> f64 {
+        self.initial_learning_rate * self.gamma.powi(self.steps as i32)
+    }
+
+    fn step(&mut self, _loss: f64) {
+        self.steps += 1;
+    }
+}
+
+// SGDR: https://arxiv.org/abs/1608.03983
+#[derive(Debug)]
+pub struct CosineAnnealingWarmRestarts {
+    max_learning_rate: f64,
+    min_learning_rate: f64,
+    period_mult: usize,
+    period: usize,
+    steps_in_period: usize,
+}
+impl CosineAnnealingWarmRestarts {
+    pub fn new(
+        max_learning_rate: f64,
+        min_learning_rate: f64,
+        first_period: usize,
+        period_mult: usize,
+    ) -> Self {
+        assert_ne!(first_period, 0);
+        assert_ne!(period_mult, 0);
+        CosineAnnealingWarmRestarts {
+            max_learning_rate,
+            min_learning_rate,
+            period_mult,
+            period: first_period,
+            steps_in_period: 0,
+        }
+    }
+}
+impl LrScheduler for CosineAnnealingWarmRestarts {
+    fn learning_rate(&self) -> f64 {
+        let progress = (self.steps_in_period as f64) / (self.period as f64);
+        self.min_learning_rate
+            + (self.max_learning_rate - self.min_learning_rate) * (1.0 + (PI * progress).cos())
+                / 2.0
+    }
+
+    fn step(&mut self, _loss: f64) {
+        self.steps_in_period += 1;
+        if self.steps_in_period == self.period {
+            self.steps_in_period = 0;
+            self.period *= self.period_mult;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LinearWarmup<S> {
+    inner: S,
+    warmup_steps: usize,
+    steps: usize,
+}
+impl<S: LrScheduler> LinearWarmup<S> {
+    // `inner` starts its own schedule once the warmup is over
+    pub fn new(inner: S, warmup_steps: usize) -> Self {
+        LinearWarmup {
+            inner,
+            warmup_steps,
+            steps: 0,
+        }
+    }
+}
+impl<S: LrScheduler> LrScheduler for LinearWarmup<S> {
+    fn learning_rate(&self) -> f64 {
+        if self.steps < self.warmup_steps {
+            self.inner.learning_rate() * ((self.steps + 1) as f64) / (self.warmup_steps as f64)
+        } else {
+            self.inner.learning_rate()
+        }
+    }
+
+    fn step(&mut self, loss: f64) {
+        if self.steps < self.warmup_steps {
+            self.steps += 1;
+        } else {
+            self.inner.step(loss);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReduceOnPlateauOptions {
+    pub factor: f64,
+    pub patience: usize,
+    pub threshold: f64,
+    pub min_learning_rate: f64,
+}
+impl Default for ReduceOnPlateauOptions {
+    fn default() -> Self {
+        ReduceOnPlateauOptions {
+            factor: 0.1,
+            patience: 10,
+            threshold: 1e-4,
+            min_learning_rate: 0.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ReduceOnPlateau {
+    learning_rate: f64,
+    options: ReduceOnPlateauOptions,
+    best_loss: f64,
+    bad_steps: usize,
+}
+impl ReduceOnPlateau {
+    pub fn new(initial_learning_rate: f64, options: ReduceOnPlateauOptions) -> Self {
+        ReduceOnPlateau {
+            learning_rate: initial_learning_rate,
+            options,
+            best_loss: f64::INFINITY,
+            bad_steps: 0,
+        }
+    }
+}
+impl LrScheduler for ReduceOnPlateau {
+    fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    fn step(&mut self, loss: f64) {
+        if loss < self.best_loss - self.options.threshold {
+            self.best_loss = loss;
+            self.bad_steps = 0;
+            return;
+        }
+
+        self.bad_steps += 1;
+        if self.bad_steps > self.options.patience {
+            self.learning_rate =
+                (self.learning_rate * self.options.factor).max(self.options.min_learning_rate);
+            self.bad_steps = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use optimize::Sgd;
+
+    fn learning_rates<S: LrScheduler>(scheduler: &mut S, losses: &[f64]) -> Vec<f64> {
+        let mut lrs = Vec::new();
+        for &loss in losses {
+            lrs.push(scheduler.learning_rate());
+            scheduler.step(loss);
+        }
+        lrs
+    }
+
+    fn assert_approx_eq(actual: &[f64], expected: &[f64]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(
+                (a - e).abs() < 1e-12,
+                "actual={:?}, expected={:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn step_decay_works() {
+        let mut s = StepDecay::new(1.0, 2, 0.5);
+        assert_approx_eq(
+            &learning_rates(&mut s, &[0.0; 5]),
+            &[1.0, 1.0, 0.5, 0.5, 0.25],
+        );
+    }
+
+    #[test]
+    fn exponential_decay_works() {
+        let mut s = ExponentialDecay::new(1.0, 0.5);
+        assert_approx_eq(&learning_rates(&mut s, &[0.0; 4]), &[1.0, 0.5, 0.25, 0.125]);
+    }
+
+    #[test]
+    fn cosine_annealing_warm_restarts_works() {
+        let mut s = CosineAnnealingWarmRestarts::new(1.0, 0.0, 2, 2);
+        assert_approx_eq(
+            &learning_rates(&mut s, &[0.0; 7]),
+            &[
+                1.0,
+                0.5,
+                1.0,
+                0.8535533905932737,
+                0.5,
+                0.14644660940672627,
+                1.0,
+            ],
+        );
+    }
+
+    #[test]
+    fn linear_warmup_works() {
+        let mut s = LinearWarmup::new(ExponentialDecay::new(1.0, 0.5), 4);
+        assert_approx_eq(
+            &learning_rates(&mut s, &[0.0; 6]),
+            &[0.25, 0.5, 0.75, 1.0, 1.0, 0.5],
+        );
+    }
+
+    #[test]
+    fn reduce_on_plateau_works() {
+        let options = ReduceOnPlateauOptions {
+            factor: 0.5,
+            patience: 1,
+            ..Default::default()
+        };
+        let mut s = ReduceOnPlateau::new(1.0, options);
+        assert_approx_eq(
+            &learning_rates(&mut s, &[3.0, 2.0, 2.0, 2.0, 1.0, 1.0, 1.0]),
+            &[1.0, 1.0, 1.0, 1.0, 0.5, 0.5, 0.5],
+        );
+        assert_eq!(s.learning_rate(), 0.25);
+    }
+
+    #[test]
+    fn apply_works() {
+        let mut optimizer = Sgd::new(1.0);
+        let mut s = ExponentialDecay::new(1.0, 0.5);
+        s.step(0.0);
+        s.apply(&mut optimizer);
+        assert_eq!(optimizer.learning_rate(), 0.5);
+    }
+}
@@ -0,0 +1,30 @@
+use rand::Rng;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use random;
+
+// A fresh directory under the system temp directory, removed on drop (even if the test panics)
+#[derive(Debug)]
+pub struct TempDir(PathBuf);
+impl TempDir {
+    pub fn new(prefix: &str) -> Self {
+        let path = env::temp_dir().join(format!("{}-{}", prefix, random::new_rng().gen::<u64>()));
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.0.join(path)
+    }
+}
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}